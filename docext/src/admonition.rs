@@ -0,0 +1,184 @@
+use {
+    crate::splice,
+    pulldown_cmark::{Event, Options, Parser, Tag, TagEnd},
+    regex::Regex,
+    std::ops::Range,
+};
+
+/// Render GitHub-style admonitions, such as `> [!NOTE]` or `> [!WARNING]`.
+///
+/// The blockquotes are replaced with callout HTML containing an icon and a
+/// title, while the rest of the blockquote is kept as markdown. Only top-level
+/// blockquotes are considered, since the replacement HTML can't be indented
+/// to fit inside of lists.
+pub fn render(doc: &str, opts: Options) -> String {
+    let quote_line = Regex::new(r"^\s{0,4}>\s?(?<rest>.*)$").unwrap();
+    let marker = Regex::new(r"^\s*\[!(?<kind>[A-Za-z]+)\]\s*$").unwrap();
+
+    // Blockquotes which turned out to be admonitions, along with their
+    // replacements.
+    let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+    // Depth of nested container blocks.
+    let mut depth = 0;
+    for (ev, range) in Parser::new_ext(doc, opts).into_offset_iter() {
+        match ev {
+            Event::Start(Tag::BlockQuote) if depth == 0 => {
+                depth += 1;
+                let mut lines = doc[range.clone()].lines().map(|line| {
+                    quote_line
+                        .captures(line)
+                        .map_or(line, |captures| captures.name("rest").unwrap().as_str())
+                });
+                let Some(kind) = lines
+                    .next()
+                    .and_then(|line| marker.captures(line))
+                    .and_then(|captures| Kind::parse(&captures["kind"]))
+                else {
+                    continue;
+                };
+
+                // The blank lines are needed for the same reason as in `theorem::open`.
+                let mut html = format!(
+                    "<div class=\"docext-admonition {}\">\n<p \
+                     class=\"docext-admonition-title\">{} {}</p>\n\n",
+                    kind.class(),
+                    kind.icon(),
+                    kind.title()
+                );
+                for line in lines {
+                    html.push_str(line);
+                    html.push('\n');
+                }
+                html.push_str("\n</div>\n\n");
+                replacements.push((range, html));
+            }
+            Event::Start(Tag::BlockQuote | Tag::List(..) | Tag::FootnoteDefinition(..)) => {
+                depth += 1
+            }
+            Event::End(TagEnd::BlockQuote | TagEnd::List(..) | TagEnd::FootnoteDefinition) => {
+                depth -= 1
+            }
+            _ => {}
+        }
+    }
+
+    if replacements.is_empty() {
+        return doc.to_owned();
+    }
+
+    let mut result = splice(doc, replacements);
+    result.push_str(STYLE);
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl Kind {
+    fn parse(kind: &str) -> Option<Self> {
+        match kind.to_ascii_uppercase().as_str() {
+            "NOTE" => Some(Kind::Note),
+            "TIP" => Some(Kind::Tip),
+            "IMPORTANT" => Some(Kind::Important),
+            "WARNING" => Some(Kind::Warning),
+            "CAUTION" => Some(Kind::Caution),
+            _ => None,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Kind::Note => "Note",
+            Kind::Tip => "Tip",
+            Kind::Important => "Important",
+            Kind::Warning => "Warning",
+            Kind::Caution => "Caution",
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            Kind::Note => "ℹ️",
+            Kind::Tip => "💡",
+            Kind::Important => "❗",
+            Kind::Warning => "⚠️",
+            Kind::Caution => "🛑",
+        }
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            Kind::Note => "docext-note",
+            Kind::Tip => "docext-tip",
+            Kind::Important => "docext-important",
+            Kind::Warning => "docext-warning",
+            Kind::Caution => "docext-caution",
+        }
+    }
+}
+
+/// Styling shared by all admonitions, using the same colors as GitHub.
+const STYLE: &str = r#"
+<style>
+.docext-admonition {
+    margin: 0.75em 0;
+    padding: 0.25em 1em;
+    border-left: 4px solid var(--docext-admonition-color);
+}
+.docext-admonition-title {
+    font-weight: bold;
+    color: var(--docext-admonition-color);
+}
+.docext-note { --docext-admonition-color: #1f6feb; }
+.docext-tip { --docext-admonition-color: #238636; }
+.docext-important { --docext-admonition-color: #8957e5; }
+.docext-warning { --docext-admonition-color: #9e6a03; }
+.docext-caution { --docext-admonition-color: #da3633; }
+</style>"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn render(doc: &str) -> String {
+        super::render(doc, Options::empty())
+    }
+
+    /// Renders an admonition with its title, icon and contents.
+    #[test]
+    fn warning() {
+        let doc = render(" > [!WARNING]\n > Be careful.\n\n After\n");
+        assert!(doc.starts_with(
+            "<div class=\"docext-admonition docext-warning\">\n<p \
+             class=\"docext-admonition-title\">⚠️ Warning</p>\n\nBe careful.\n\n</div>\n\n"
+        ));
+        assert!(doc.contains("\n After\n"));
+    }
+
+    /// Accepts lowercase markers.
+    #[test]
+    fn lowercase() {
+        assert!(render(" > [!tip]\n > Hello\n").contains("docext-tip"));
+    }
+
+    /// Leaves regular blockquotes, unknown markers and nested blockquotes
+    /// unchanged.
+    #[test]
+    fn unchanged() {
+        for doc in [
+            " > Just a quote\n",
+            " > [!FOO]\n > Hello\n",
+            " > > [!NOTE]\n > > Hello\n",
+            " - > [!NOTE]\n   > Hello\n",
+            " ```\n > [!NOTE]\n ```\n",
+        ] {
+            assert_eq!(render(doc), doc);
+        }
+    }
+}
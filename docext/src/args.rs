@@ -0,0 +1,49 @@
+use {
+    proc_macro::TokenStream,
+    quote::ToTokens,
+    syn::{parse::Parser, punctuated::Punctuated, Expr, ExprLit, Lit, Meta, Token},
+};
+
+/// Arguments of the `#[docext(...)]` attribute, such as `#[docext(toc)]`.
+#[derive(Debug, Default)]
+pub struct Args {
+    /// Generate a table of contents, even if there is no `[[toc]]` marker.
+    pub toc: bool,
+    /// Link images to their data directly instead of loading them with a
+    /// script, so that they are shown without JavaScript.
+    pub data_urls: bool,
+    /// Directory which relative paths are resolved against, relative to the
+    /// crate root, such as `#[docext(base = "src/geometry")]`.
+    pub base: Option<String>,
+}
+
+impl Args {
+    pub fn parse(attr: TokenStream) -> Self {
+        let metas = Punctuated::<Meta, Token![,]>::parse_terminated
+            .parse(attr)
+            .unwrap_or_else(|err| panic!("failed to parse #[docext] arguments: {err}"));
+
+        let mut args = Self::default();
+        for meta in metas {
+            match &meta {
+                Meta::Path(path) if path.is_ident("toc") => args.toc = true,
+                Meta::Path(path) if path.is_ident("data_urls") => args.data_urls = true,
+                Meta::NameValue(name_value) if name_value.path.is_ident("base") => {
+                    let Expr::Lit(ExprLit {
+                        lit: Lit::Str(base),
+                        ..
+                    }) = &name_value.value
+                    else {
+                        panic!(r#"#[docext] argument "base" must be a string"#);
+                    };
+                    args.base = Some(base.value());
+                }
+                _ => panic!(
+                    r#"unknown #[docext] argument: "{}""#,
+                    meta.to_token_stream()
+                ),
+            }
+        }
+        args
+    }
+}
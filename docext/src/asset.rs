@@ -0,0 +1,170 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+};
+
+/// Assets written by this process, with their sizes, so that each is only
+/// written once.
+static WRITTEN: Mutex<BTreeMap<PathBuf, usize>> = Mutex::new(BTreeMap::new());
+
+/// Content hash of an asset, used to identify it. The hash is stable across
+/// builds and compiler versions, unlike the hashers of the standard library.
+pub fn hash(data: &[u8]) -> String {
+    // 64-bit FNV-1a.
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{hash:016x}")
+}
+
+/// Store an asset in the crate-wide asset store, so that items using it only
+/// need to reference its hash. The asset is written as a script into the
+/// documentation output, which the image script loads on demand. The value is
+/// either `img`, a data URL, `svg`, SVG markup to be inlined, or `script`, the
+/// source of a script to be run. Return the hash of the asset.
+///
+/// Assets are only written while rustdoc is running, since other builds don't
+/// produce pages which could use them. Rustdoc is recognized by the name of the
+/// executable, and the store is placed in the output directory given by its
+/// arguments. Cargo doesn't know about the store, so if it is deleted while
+/// cargo considers the documentation up to date, the assets aren't written
+/// again. The image script then reports each missing asset in the browser
+/// console, and rebuilding the documentation, such as with
+/// `cargo clean --doc && cargo doc`, restores them.
+pub fn share(kind: &str, value: &str) -> String {
+    let hash = hash(format!("{kind}:{value}").as_bytes());
+    let Some(dir) = store_dir() else {
+        return hash;
+    };
+    let script = format!(
+        "(window.docextAssets = window.docextAssets || {{}})[\"{hash}\"] = {{ {kind}: {} }};\n",
+        js_string(value)
+    );
+    store(&dir.join(format!("{hash}.js")), &script);
+    hash
+}
+
+/// Write an asset script to the store, unless it is already there. Existing
+/// files are only kept if their content matches, so that files left truncated
+/// by an interrupted build are replaced. Scripts are written to a temporary
+/// file first and then moved into place, so that they are never read while
+/// half-written.
+fn store(path: &Path, script: &str) {
+    let mut written = WRITTEN.lock().unwrap_or_else(|err| err.into_inner());
+    match written.get(path) {
+        Some(len) if *len == script.len() => return,
+        Some(_) => panic!(
+            r#"asset hash collision: "{}", please report this as a bug"#,
+            path.to_string_lossy()
+        ),
+        None => {}
+    }
+    if fs::read(path).is_ok_and(|existing| existing == script.as_bytes()) {
+        written.insert(path.to_owned(), script.len());
+        return;
+    }
+    let tmp = path.with_extension(format!("{}.tmp", process::id()));
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&tmp, script))
+        .and_then(|()| fs::rename(&tmp, path))
+        .unwrap_or_else(|err| {
+            let _ = fs::remove_file(&tmp);
+            panic!(
+                r#"failed to write asset: "{}": {err}"#,
+                path.to_string_lossy()
+            )
+        });
+    written.insert(path.to_owned(), script.len());
+}
+
+/// Path of the asset store relative to the documentation root, which is where
+/// the image script looks for assets.
+pub fn store_path() -> String {
+    format!(
+        "{}/docext/",
+        env::var("CARGO_CRATE_NAME").unwrap_or_default()
+    )
+}
+
+/// The directory which assets are written to, or `None` if rustdoc isn't
+/// generating documentation.
+fn store_dir() -> Option<PathBuf> {
+    let exe = env::current_exe().ok()?;
+    if !exe.file_stem()?.to_string_lossy().starts_with("rustdoc") {
+        return None;
+    }
+    // Cargo passes the output directory as `-o <dir>`.
+    let mut args = env::args_os();
+    let out = loop {
+        let arg = args.next()?;
+        if arg == "-o" || arg == "--out-dir" {
+            break PathBuf::from(args.next()?);
+        }
+        if let Some(out) = arg.to_str().and_then(|arg| arg.strip_prefix("--out-dir=")) {
+            break PathBuf::from(out);
+        }
+    };
+    Some(out.join(store_path()))
+}
+
+/// Encode text as a JavaScript string literal.
+fn js_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\u{2028}' => result.push_str("\\u2028"),
+            '\u{2029}' => result.push_str("\\u2029"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Hashes are stable.
+    #[test]
+    fn stable_hash() {
+        assert_eq!(hash(b""), "cbf29ce484222325");
+        assert_eq!(hash(b"a"), "af63dc4c8601ec8c");
+        assert_ne!(hash(b"img:a"), hash(b"svg:a"));
+    }
+
+    /// Replaces assets whose content doesn't match, such as truncated ones.
+    #[test]
+    fn replace_truncated() {
+        let dir = env::temp_dir().join(format!("docext-assets-{}", process::id()));
+        let path = dir.join("a.js");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "window.a = ").unwrap();
+        store(&path, "window.a = 1;\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "window.a = 1;\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Encodes JavaScript strings.
+    #[test]
+    fn strings() {
+        assert_eq!(
+            js_string("<svg a=\"b\">\n\\</svg>\u{2028}"),
+            r#""<svg a=\"b\">\n\\</svg>\u2028""#
+        );
+    }
+}
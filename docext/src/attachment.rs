@@ -0,0 +1,128 @@
+use {
+    crate::{attrs::escape, budget, config::Budgets},
+    std::{fs, path::Path},
+};
+
+/// Extensions of the files which are embedded when linked to, unless configured
+/// otherwise.
+pub const EXTENSIONS: &[&str] = &[
+    "csv", "json", "pdf", "ron", "toml", "tsv", "txt", "xml", "yaml", "yml", "zip",
+];
+
+/// Extensions of the files which links are checked for, besides the embedded
+/// ones. Links with other extensions aren't taken to be files, since many of
+/// them aren't, such as `[site](example.com)`.
+const CHECKED: &[&str] = &[
+    "avif", "bib", "bmp", "c", "cpp", "css", "gif", "glb", "gltf", "h", "ico", "jpeg", "jpg", "js",
+    "lock", "md", "mp3", "mp4", "obj", "ogg", "png", "py", "rs", "sh", "stl", "svg", "wasm", "wav",
+    "webm", "webp",
+];
+
+/// The extension of the local file a link destination refers to, in lowercase,
+/// or `None` if it refers to a URL, an anchor, an intra-doc link such as
+/// `crate::Item`, another page of the documentation, or a path without an
+/// extension, such as a directory.
+pub fn extension(dest: &str) -> Option<String> {
+    let path = file_path(dest);
+    if path.contains("::") || path.ends_with(".html") || path.ends_with(".htm") {
+        return None;
+    }
+    let (_, ext) = path.rsplit('/').next()?.rsplit_once('.')?;
+    (!ext.is_empty() && ext.bytes().all(|b| b.is_ascii_alphanumeric()))
+        .then(|| ext.to_ascii_lowercase())
+}
+
+/// Whether links to files with the given extension are checked to exist, when
+/// they aren't embedded.
+pub fn is_checked(ext: &str) -> bool {
+    EXTENSIONS.contains(&ext) || CHECKED.contains(&ext)
+}
+
+/// The path of the file a link refers to, without the fragment and query.
+pub fn file_path(dest: &str) -> &str {
+    dest.split(['#', '?']).next().unwrap()
+}
+
+/// Load a linked file to be embedded into the doc comment, checking it against
+/// the per-file budget. The name is the path used in the doc comment. Return
+/// the MIME type and the data.
+pub fn load(path: &Path, name: &str, budgets: &Budgets) -> (&'static str, Vec<u8>) {
+    let metadata = fs::metadata(path).unwrap_or_else(|_| {
+        panic!(
+            r#"failed to stat file: "{}", is the file missing?"#,
+            path.to_string_lossy()
+        )
+    });
+    budget::check_file(budgets, "linked", name, metadata.len());
+    let data = fs::read(path).unwrap_or_else(|_| {
+        panic!(
+            r#"failed to read file: "{}", is the file missing?"#,
+            path.to_string_lossy()
+        )
+    });
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    (mime(ext.as_deref().unwrap_or_default()), data)
+}
+
+/// Render the opening tag of a link which downloads the file with the given
+/// name.
+pub fn html(href: &str, file_name: &str, title: &str) -> String {
+    let mut html = format!(
+        r#"<a href="{}" download="{}""#,
+        escape(href),
+        escape(file_name)
+    );
+    if !title.is_empty() {
+        html.push_str(&format!(r#" title="{}""#, escape(title)));
+    }
+    html.push('>');
+    html
+}
+
+/// Get the MIME type of a file based on its extension.
+fn mime(ext: &str) -> &'static str {
+    match ext {
+        "csv" => "text/csv",
+        "tsv" => "text/tab-separated-values",
+        "txt" | "ron" => "text/plain",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        "toml" => "application/toml",
+        "xml" => "application/xml",
+        "yaml" | "yml" => "application/yaml",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Tells files apart from other links.
+    #[test]
+    fn files() {
+        assert_eq!(extension("assets/config.TOML").as_deref(), Some("toml"));
+        assert_eq!(extension("data.csv#L2").as_deref(), Some("csv"));
+        assert_eq!(extension("../README.md").as_deref(), Some("md"));
+        assert_eq!(extension("../guide/"), None);
+        assert_eq!(extension("../v1.2/guide"), None);
+        assert_eq!(extension("#section"), None);
+        assert_eq!(extension("crate::Item"), None);
+        assert_eq!(extension("Item#fig:a"), None);
+        assert_eq!(extension("f64::consts"), None);
+        assert_eq!(extension("../other/struct.A.html#method.b"), None);
+        assert!(!is_checked("com"));
+    }
+
+    /// Renders download links.
+    #[test]
+    fn link() {
+        assert_eq!(
+            html("data:text/csv;base64,YQ==", "a \"b\".csv", ""),
+            r#"<a href="data:text/csv;base64,YQ==" download="a &quot;b&quot;.csv">"#
+        );
+    }
+}
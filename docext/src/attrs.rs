@@ -0,0 +1,388 @@
+/// Attributes of an image, given in braces after it, such as
+/// `![diagram](img/a.png){width=400 align=center}`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Attrs {
+    /// The id of the image, given as `#id`.
+    pub id: Option<String>,
+    /// Classes of the image, given as `.class`.
+    pub classes: Vec<String>,
+    pub width: Option<String>,
+    pub height: Option<String>,
+    pub align: Option<Align>,
+    /// Additional inline styles.
+    pub style: Option<String>,
+    /// Images for other pixel densities, such as `img/a.png 1x, img/a@2x.png
+    /// 2x`.
+    pub srcset: Option<String>,
+    /// Image shown in place of a 3D model until it is loaded, or if it can't
+    /// be shown.
+    pub poster: Option<String>,
+    /// Let the reader rotate and zoom a 3D model, given as `controls=orbit`.
+    pub orbit: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Attrs {
+    /// Parse the attributes at the start of the text, which directly follows an
+    /// image. Return the attributes and the length of the text they span, or
+    /// `None` if the text doesn't start with attributes. Text in braces is only
+    /// taken to be attributes if it consists of `key=value`, `#id` and `.class`
+    /// tokens, so that prose such as `{see note}` is left alone.
+    pub fn parse(text: &str) -> Option<(Self, usize)> {
+        let rest = text.strip_prefix('{')?;
+        // The attributes can be wrapped onto multiple lines, but not span paragraphs.
+        let end = rest.find('}')?;
+        if rest[..end]
+            .lines()
+            .skip(1)
+            .any(|line| line.trim().is_empty())
+        {
+            return None;
+        }
+        let tokens = tokens(&rest[..end]);
+        if tokens.is_empty() || !tokens.iter().all(|token| is_attr(token)) {
+            return None;
+        }
+        let mut attrs = Self::default();
+        for token in tokens {
+            if let Some(id) = token.strip_prefix('#') {
+                attrs.id = Some(id.to_owned());
+                continue;
+            }
+            if let Some(class) = token.strip_prefix('.') {
+                attrs.classes.push(class.to_owned());
+                continue;
+            }
+            let (key, value) = token.split_once('=').unwrap();
+            let value = value.trim_matches('"');
+            let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+            match key {
+                "width" => attrs.width = Some(size(key, value)),
+                "height" => attrs.height = Some(size(key, value)),
+                "align" => {
+                    attrs.align = Some(match value.as_str() {
+                        "left" => Align::Left,
+                        "center" => Align::Center,
+                        "right" => Align::Right,
+                        _ => panic!(
+                            r#"invalid image alignment: "{value}", expected "left", "center" or "right""#
+                        ),
+                    })
+                }
+                "style" => attrs.style = Some(value),
+                "srcset" => attrs.srcset = Some(value),
+                "poster" => attrs.poster = Some(value),
+                "controls" => {
+                    attrs.orbit = match value.as_str() {
+                        "orbit" => true,
+                        "none" => false,
+                        _ => panic!(
+                            r#"invalid model controls: "{value}", expected "orbit" or "none""#
+                        ),
+                    }
+                }
+                _ => panic!(r#"unknown image attribute: "{key}""#),
+            }
+        }
+        Some((attrs, end + 2))
+    }
+
+    /// Render an image with these attributes as an HTML tag.
+    pub fn img(&self, src: &str, alt: &str, title: &str) -> String {
+        self.check_model_attrs(src);
+        let mut html = format!(r#"<img src="{}" alt="{}""#, escape(src), escape(alt));
+        if !title.is_empty() {
+            html.push_str(&format!(r#" title="{}""#, escape(title)));
+        }
+        if let Some(srcset) = &self.srcset {
+            html.push_str(&format!(r#" srcset="{}""#, escape(srcset)));
+        }
+        self.push(&mut html, None, true, Vec::new());
+        html.push('>');
+        html
+    }
+
+    /// Render a video or audio file with these attributes as an HTML element
+    /// with controls, `tag` being `video` or `audio`. The alt text labels the
+    /// element, and is shown by browsers which can't play the file.
+    pub fn media(&self, tag: &str, src: &str, alt: &str, title: &str) -> String {
+        if self.srcset.is_some() {
+            panic!(r#"image attribute "srcset" is not supported for {tag}: "{src}""#);
+        }
+        self.check_model_attrs(src);
+        let mut html = format!(r#"<{tag} controls src="{}""#, escape(src));
+        if !alt.is_empty() {
+            html.push_str(&format!(r#" aria-label="{}""#, escape(alt)));
+        }
+        if !title.is_empty() {
+            html.push_str(&format!(r#" title="{}""#, escape(title)));
+        }
+        self.push(&mut html, None, true, Vec::new());
+        html.push_str(&format!(">{}</{tag}>", escape(alt)));
+        html
+    }
+
+    /// Render a 3D model with these attributes as an element which the viewer
+    /// script draws the model into. The poster image, or else the alt text, is
+    /// shown until the model is loaded, and if it can't be shown.
+    pub fn model(&self, src: &str, alt: &str, title: &str) -> String {
+        if self.srcset.is_some() {
+            panic!(r#"image attribute "srcset" is not supported for 3D models: "{src}""#);
+        }
+        let mut html = format!(
+            r#"<span data-model="{}" role="img" aria-label="{}""#,
+            escape(src),
+            escape(alt)
+        );
+        if !title.is_empty() {
+            html.push_str(&format!(r#" title="{}""#, escape(title)));
+        }
+        if self.orbit {
+            html.push_str(r#" data-controls="orbit""#);
+        }
+        let mut style = vec!["display: inline-block".to_owned()];
+        if self.width.is_none() {
+            style.push("width: 400px".to_owned());
+        }
+        if self.height.is_none() {
+            style.push("height: 300px".to_owned());
+        }
+        self.push(&mut html, Some("docext-model"), false, style);
+        html.push('>');
+        match &self.poster {
+            Some(poster) => html.push_str(&format!(
+                r#"<img src="{}" alt="{}" style="width: 100%; height: 100%; object-fit: contain">"#,
+                escape(poster),
+                escape(alt)
+            )),
+            None => html.push_str(&escape(alt)),
+        }
+        html.push_str("</span>");
+        html
+    }
+
+    /// Error on attributes which only apply to 3D models.
+    fn check_model_attrs(&self, src: &str) {
+        if self.poster.is_some() || self.orbit {
+            panic!(
+                r#"image attributes "poster" and "controls" are only supported for 3D models: "{src}""#
+            );
+        }
+    }
+
+    /// Add the attributes which all elements support to an HTML tag, along with
+    /// the given class and styles. Sizes are given as attributes if the element
+    /// supports them, as styles otherwise.
+    fn push(&self, html: &mut String, class: Option<&str>, sized: bool, mut style: Vec<String>) {
+        if let Some(id) = &self.id {
+            html.push_str(&format!(r#" id="{}""#, escape(id)));
+        }
+        let classes: Vec<&str> = class
+            .into_iter()
+            .chain(self.classes.iter().map(String::as_str))
+            .collect();
+        if !classes.is_empty() {
+            html.push_str(&format!(r#" class="{}""#, escape(&classes.join(" "))));
+        }
+
+        for (name, value) in [("width", &self.width), ("height", &self.height)] {
+            match value {
+                // Plain numbers are pixels, which HTML supports as attributes.
+                Some(value) if value.bytes().all(|b| b.is_ascii_digit()) && sized => {
+                    html.push_str(&format!(r#" {name}="{value}""#))
+                }
+                Some(value) if value.bytes().all(|b| b.is_ascii_digit()) => {
+                    style.push(format!("{name}: {value}px"))
+                }
+                Some(value) => style.push(format!("{name}: {value}")),
+                None => {}
+            }
+        }
+        match self.align {
+            Some(Align::Left) => style.push("float: left; margin-right: 1em".to_owned()),
+            Some(Align::Center) => style.push("display: block; margin: 0 auto".to_owned()),
+            Some(Align::Right) => style.push("float: right; margin-left: 1em".to_owned()),
+            None => {}
+        }
+        style.extend(self.style.clone());
+        if !style.is_empty() {
+            html.push_str(&format!(r#" style="{}""#, escape(&style.join("; "))));
+        }
+    }
+}
+
+/// Split the attributes at whitespace, keeping quoted values together.
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if let Some(start) = start.take() {
+                    tokens.push(&text[start..i]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(start) = start {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+/// Whether a token is a `key=value` pair, an `#id` or a `.class`.
+fn is_attr(token: &str) -> bool {
+    let is_name = |name: &str| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':'))
+    };
+    if let Some(name) = token.strip_prefix('#').or_else(|| token.strip_prefix('.')) {
+        return is_name(name);
+    }
+    token
+        .split_once('=')
+        .is_some_and(|(key, value)| is_name(key) && !value.is_empty())
+}
+
+/// Validate a width or height, which is a number of pixels or a CSS length.
+/// Numbers with decimals are pixels as well, but can only be given as styles,
+/// so they get the unit.
+fn size(key: &str, value: String) -> String {
+    let unit = ["%", "px", "em", "rem", "vw", "vh", "ch"]
+        .into_iter()
+        .find(|unit| value.ends_with(unit));
+    let number = &value[..value.len() - unit.map_or(0, str::len)];
+    if !number.bytes().all(|b| b.is_ascii_digit() || b == b'.') || number.parse::<f64>().is_err() {
+        panic!(r#"invalid image {key}: "{value}", expected e.g. "400", "50%" or "20em""#);
+    }
+    if unit.is_none() && number.contains('.') {
+        return format!("{value}px");
+    }
+    value
+}
+
+/// Escape text for use in an HTML attribute.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Parses all kinds of attributes.
+    #[test]
+    fn parse() {
+        let (attrs, len) = Attrs::parse(
+            r#"{#fig .wide width=400 height=50% align=center style="border: 1px solid"} rest"#,
+        )
+        .unwrap();
+        assert_eq!(len, 72);
+        assert_eq!(
+            attrs,
+            Attrs {
+                id: Some("fig".to_owned()),
+                classes: vec!["wide".to_owned()],
+                width: Some("400".to_owned()),
+                height: Some("50%".to_owned()),
+                align: Some(Align::Center),
+                style: Some("border: 1px solid".to_owned()),
+                srcset: None,
+                poster: None,
+                orbit: false,
+            }
+        );
+    }
+
+    /// Ignores text which doesn't start with attributes.
+    #[test]
+    fn no_attrs() {
+        assert_eq!(Attrs::parse(" {width=400}"), None);
+        assert_eq!(Attrs::parse("{width=400\n \n}"), None);
+        assert_eq!(Attrs::parse("{see note}"), None);
+        assert_eq!(Attrs::parse("{width=400 and more}"), None);
+        assert_eq!(Attrs::parse("{}"), None);
+    }
+
+    /// Gives decimal pixel sizes their unit.
+    #[test]
+    fn sizes() {
+        let (attrs, _) = Attrs::parse("{width=1.5 height=2.5em}").unwrap();
+        assert_eq!(attrs.width.as_deref(), Some("1.5px"));
+        assert_eq!(
+            attrs.img("a.png", "a", ""),
+            r#"<img src="a.png" alt="a" style="width: 1.5px; height: 2.5em">"#
+        );
+    }
+
+    /// Errors on malformed sizes.
+    #[test]
+    #[should_panic(expected = r#"invalid image width: "1.2.3""#)]
+    fn invalid_size() {
+        Attrs::parse("{width=1.2.3}");
+    }
+
+    /// Renders attributes as HTML attributes and styles.
+    #[test]
+    fn img() {
+        let (attrs, _) = Attrs::parse("{width=400 height=10em align=right}").unwrap();
+        assert_eq!(
+            attrs.img("img/a b.png", "A \"quote\"", ""),
+            r#"<img src="img/a b.png" alt="A &quot;quote&quot;" width="400" style="height: 10em; float: right; margin-left: 1em">"#
+        );
+    }
+
+    /// Renders the images for other pixel densities, given on multiple lines.
+    #[test]
+    fn srcset() {
+        let (attrs, _) = Attrs::parse("{srcset=\"a.png 1x,\n a@2x.png 2x\"}").unwrap();
+        assert_eq!(
+            attrs.img("a.png", "a", ""),
+            r#"<img src="a.png" alt="a" srcset="a.png 1x, a@2x.png 2x">"#
+        );
+    }
+
+    /// Renders videos and audio files with controls.
+    #[test]
+    fn media() {
+        let (attrs, _) = Attrs::parse("{width=320 .demo}").unwrap();
+        assert_eq!(
+            attrs.media("video", "run.webm", "A <run>", "Run"),
+            r#"<video controls src="run.webm" aria-label="A &lt;run&gt;" title="Run" class="demo" width="320">A &lt;run&gt;</video>"#
+        );
+    }
+
+    /// Renders 3D models with a poster and sizes as styles.
+    #[test]
+    fn model() {
+        let (attrs, _) =
+            Attrs::parse("{width=320 height=50% .part poster=img/a.png controls=orbit}").unwrap();
+        assert_eq!(
+            attrs.model("a.glb", "A", ""),
+            r#"<span data-model="a.glb" role="img" aria-label="A" data-controls="orbit" class="docext-model part" style="display: inline-block; width: 320px; height: 50%"><img src="img/a.png" alt="A" style="width: 100%; height: 100%; object-fit: contain"></span>"#
+        );
+    }
+
+    /// Errors on unknown attributes, which are most likely typos.
+    #[test]
+    #[should_panic(expected = r#"unknown image attribute: "widht""#)]
+    fn unknown() {
+        Attrs::parse("{widht=400}");
+    }
+}
@@ -0,0 +1,591 @@
+use {
+    crate::{attrs::escape, config::CitationStyle},
+    regex::{Captures, Regex},
+    std::{collections::HashMap, fs, path::Path},
+};
+
+/// Entries of a BibTeX file, indexed by their citation keys.
+#[derive(Debug)]
+pub struct Bibliography {
+    entries: HashMap<String, Entry>,
+}
+
+/// A single BibTeX entry, such as `@book{golub2013, ...}`.
+#[derive(Debug)]
+struct Entry {
+    /// Fields of the entry with lowercase names.
+    fields: HashMap<String, String>,
+}
+
+impl Bibliography {
+    /// Load and parse the BibTeX file at the given path.
+    pub fn load(path: &Path) -> Self {
+        let src = fs::read_to_string(path).unwrap_or_else(|_| {
+            panic!(
+                r#"failed to read bibliography: "{}", is the file missing?"#,
+                path.to_string_lossy()
+            )
+        });
+        Self::parse(&src).unwrap_or_else(|err| {
+            panic!(
+                r#"failed to parse bibliography: "{}": {err}"#,
+                path.to_string_lossy()
+            )
+        })
+    }
+
+    fn parse(src: &str) -> Result<Self, String> {
+        let mut entries = HashMap::new();
+        let mut rest = src;
+        while let Some(at) = rest.find('@') {
+            rest = &rest[at + 1..];
+            let open = rest
+                .find(['{', '('])
+                .ok_or("expected an opening brace after entry type")?;
+            let kind = rest[..open].trim().to_ascii_lowercase();
+            let close = if rest.as_bytes()[open] == b'{' {
+                '}'
+            } else {
+                ')'
+            };
+            let end = matching(rest, open, close).ok_or("unclosed entry")?;
+            let body = &rest[open + 1..end];
+            rest = &rest[end + 1..];
+
+            if matches!(kind.as_str(), "comment" | "preamble" | "string") {
+                continue;
+            }
+
+            let (key, mut fields_src) = body
+                .split_once(',')
+                .ok_or_else(|| format!("entry without fields: {}", body.trim()))?;
+            let key = key.trim().to_owned();
+            let mut fields = HashMap::new();
+            loop {
+                fields_src = fields_src.trim_start();
+                if fields_src.is_empty() {
+                    break;
+                }
+                let (name, value) = fields_src
+                    .split_once('=')
+                    .ok_or_else(|| format!(r#"expected "=" in entry "{key}""#))?;
+                let (value, after) = parse_value(value.trim_start()).ok_or_else(|| {
+                    format!(r#"malformed field "{}" in entry "{key}""#, name.trim())
+                })?;
+                fields.insert(name.trim().to_ascii_lowercase(), value);
+                fields_src = after.trim_start();
+                fields_src = fields_src.strip_prefix(',').unwrap_or(fields_src);
+            }
+            if entries.insert(key.clone(), Entry { fields }).is_some() {
+                return Err(format!(r#"duplicate entry "{key}""#));
+            }
+        }
+        Ok(Self { entries })
+    }
+}
+
+/// Find the index of the delimiter closing the one at `open`, respecting nested
+/// braces.
+fn matching(text: &str, open: usize, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text[open + 1..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            c if c == close && depth == 0 => return Some(open + 1 + i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse a field value, which is a concatenation of braced strings, quoted
+/// strings and bare words separated by `#`. Return the value and the remaining
+/// input.
+fn parse_value(mut text: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    loop {
+        if text.starts_with('{') {
+            let end = matching(text, 0, '}')?;
+            value.push_str(&text[1..end]);
+            text = &text[end + 1..];
+        } else if let Some(quoted) = text.strip_prefix('"') {
+            let mut depth = 0;
+            let end = quoted.char_indices().find_map(|(i, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    '"' if depth == 0 => return Some(i),
+                    _ => {}
+                }
+                None
+            })?;
+            value.push_str(&quoted[..end]);
+            text = &quoted[end + 1..];
+        } else {
+            let end = text
+                .find(|c: char| c == ',' || c == '#' || c == '}' || c.is_whitespace())
+                .unwrap_or(text.len());
+            if end == 0 {
+                return None;
+            }
+            value.push_str(&text[..end]);
+            text = &text[end..];
+        }
+        text = text.trim_start();
+        match text.strip_prefix('#') {
+            Some(after) => text = after.trim_start(),
+            None => return Some((value, text)),
+        }
+    }
+}
+
+impl Entry {
+    /// Get a field with the LaTeX markup removed and HTML special characters
+    /// escaped.
+    fn field(&self, name: &str) -> Option<String> {
+        self.fields.get(name).map(|value| clean(value))
+    }
+
+    /// The value of a field holding a URL or a DOI, with braces, whitespace
+    /// and escapes of special characters removed, but not escaped for HTML.
+    fn link_field(&self, name: &str) -> Option<String> {
+        let value = self.fields.get(name)?;
+        let value: String = value
+            .replace(['{', '}'], "")
+            .replace("\\_", "_")
+            .replace("\\%", "%")
+            .replace("\\&", "&")
+            .replace("\\#", "#")
+            .split_whitespace()
+            .collect();
+        (!value.is_empty()).then_some(value)
+    }
+
+    /// The authors of the entry, or the editors if there are no authors.
+    fn names(&self) -> Vec<Name> {
+        self.fields
+            .get("author")
+            .or_else(|| self.fields.get("editor"))
+            .map(|names| split_names(names))
+            .unwrap_or_default()
+    }
+
+    /// The label used for author-year citations, such as "Golub and Van Loan,
+    /// 2013".
+    fn label(&self) -> String {
+        let names = self.names();
+        let authors = match names.as_slice() {
+            [] => self.field("title").unwrap_or_default(),
+            [a] => a.last.clone(),
+            [a, b] => format!("{} and {}", a.last, b.last),
+            [a, ..] => format!("{} et al.", a.last),
+        };
+        match self.field("year") {
+            Some(year) => format!("{authors}, {year}"),
+            None => authors,
+        }
+    }
+
+    /// The full reference, formatted as HTML.
+    fn reference(&self) -> String {
+        let mut parts = Vec::new();
+        let names: Vec<_> = self
+            .names()
+            .into_iter()
+            .map(|name| {
+                if name.first.is_empty() {
+                    name.last
+                } else {
+                    format!("{} {}", name.first, name.last)
+                }
+            })
+            .collect();
+        match names.as_slice() {
+            [] => {}
+            [a] => parts.push(a.clone()),
+            [a, b] => parts.push(format!("{a} and {b}")),
+            [rest @ .., last] => parts.push(format!("{}, and {last}", rest.join(", "))),
+        }
+        if let Some(title) = self.field("title") {
+            parts.push(title);
+        }
+
+        let mut source = Vec::new();
+        if let Some(container) = self.field("journal").or_else(|| self.field("booktitle")) {
+            source.push(format!("<em>{container}</em>"));
+        }
+        match (self.field("volume"), self.field("number")) {
+            (Some(volume), Some(number)) => source.push(format!("{volume}({number})")),
+            (Some(volume), None) => source.push(volume),
+            _ => {}
+        }
+        if let Some(pages) = self.field("pages") {
+            source.push(format!("pp. {pages}"));
+        }
+        if let Some(publisher) = self.field("publisher") {
+            source.push(publisher);
+        }
+        if let Some(year) = self.field("year") {
+            source.push(year);
+        }
+        if !source.is_empty() {
+            parts.push(source.join(", "));
+        }
+
+        let mut reference = parts.join(". ");
+        reference.push('.');
+        // Only web links are allowed, since other schemes, such as `javascript:`,
+        // could run scripts.
+        let url = self.link_field("url").filter(|url| {
+            let url = url.to_ascii_lowercase();
+            url.starts_with("https://") || url.starts_with("http://")
+        });
+        if let Some(doi) = self.link_field("doi") {
+            reference.push_str(&format!(
+                r#" <a href="{}">doi:{}</a>"#,
+                escape(&format!("https://doi.org/{doi}")),
+                escape(&doi)
+            ));
+        } else if let Some(url) = url {
+            let url = escape(&url);
+            reference.push_str(&format!(r#" <a href="{url}">{url}</a>"#));
+        }
+        reference
+    }
+}
+
+/// A name split into first and last parts, with markup removed.
+struct Name {
+    first: String,
+    last: String,
+}
+
+/// Split a BibTeX name list, such as "Golub, Gene H. and Charles F. {Van
+/// Loan}".
+fn split_names(names: &str) -> Vec<Name> {
+    split_top_level(names, " and ")
+        .into_iter()
+        .map(|name| {
+            let name = name.trim();
+            if let [last, first, ..] = split_top_level(name, ",").as_slice() {
+                return Name {
+                    first: clean(first.trim()),
+                    last: clean(last.trim()),
+                };
+            }
+            // In the "First von Last" form, the last name starts at the first lowercase
+            // word, or is the last word if there is none.
+            let words: Vec<_> = split_top_level(name, " ")
+                .into_iter()
+                .filter(|word| !word.is_empty())
+                .collect();
+            let start = words[..words.len().saturating_sub(1)]
+                .iter()
+                .position(|word| word.starts_with(char::is_lowercase))
+                .unwrap_or(words.len().saturating_sub(1));
+            Name {
+                first: clean(&words[..start].join(" ")),
+                last: clean(&words[start..].join(" ")),
+            }
+        })
+        .collect()
+}
+
+/// Split the text on a separator, ignoring separators inside of braces.
+fn split_top_level<'a>(text: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ if depth == 0 && i >= start && text[i..].starts_with(sep) => {
+                parts.push(&text[start..i]);
+                start = i + sep.len();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Remove common LaTeX markup from a BibTeX value and escape HTML special
+/// characters. Math is kept as-is, so that it can be rendered by KaTeX.
+fn clean(value: &str) -> String {
+    value
+        .replace(['{', '}'], "")
+        .replace("\\&", "&")
+        .replace("---", "—")
+        .replace("--", "–")
+        .replace('~', "\u{a0}")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Citations made in a single doc comment.
+pub struct Citations {
+    bibliography: Bibliography,
+    style: CitationStyle,
+    /// Part of the ids of the references which is unique to the doc comment,
+    /// since several items are shown on the same page, such as a type and its
+    /// methods, and can cite the same entries.
+    scope: String,
+    /// Keys of the cited entries, in order of first citation.
+    cited: Vec<String>,
+    citation: Regex,
+    cite: Regex,
+}
+
+impl Citations {
+    pub fn new(bibliography: Bibliography, style: CitationStyle, scope: &str) -> Self {
+        Self {
+            bibliography,
+            style,
+            scope: scope.to_owned(),
+            cited: Vec::new(),
+            citation: Regex::new(r"\[(?<cites>@[^\[\]]*)\](?<next>[(\[]?)").unwrap(),
+            cite: Regex::new(r"^@(?<key>[^\s,;@]+)\s*(?:,\s*(?<locator>.*))?$").unwrap(),
+        }
+    }
+
+    /// Whether the text might contain a citation.
+    pub fn contains_citation(text: &str) -> bool {
+        text.contains("[@")
+    }
+
+    /// Replace all citations in the text, such as `[@golub2013, p. 42]`, with
+    /// links to their references.
+    pub fn replace(&mut self, text: &str) -> String {
+        let citation = self.citation.clone();
+        citation
+            .replace_all(text, |captures: &Captures| {
+                if !captures["next"].is_empty() {
+                    // This is a markdown link, not a citation.
+                    return captures[0].to_owned();
+                }
+                let cites: Option<Vec<_>> = captures["cites"]
+                    .split(';')
+                    .map(|cite| self.cite.captures(cite.trim()))
+                    .collect();
+                let Some(cites) = cites else {
+                    return captures[0].to_owned();
+                };
+                let cites: Vec<_> = cites
+                    .iter()
+                    .map(|cite| {
+                        self.render(
+                            &cite["key"],
+                            cite.name("locator").map(|locator| locator.as_str()),
+                        )
+                    })
+                    .collect();
+                match self.style {
+                    CitationStyle::Numeric => format!(
+                        r#"<span class="docext-cite">&#91;{}&#93;</span>"#,
+                        cites.join("; ")
+                    ),
+                    CitationStyle::AuthorYear => {
+                        format!(r#"<span class="docext-cite">({})</span>"#, cites.join("; "))
+                    }
+                }
+            })
+            .into_owned()
+    }
+
+    /// Render a single citation of the given key.
+    fn render(&mut self, key: &str, locator: Option<&str>) -> String {
+        let Some(entry) = self.bibliography.entries.get(key) else {
+            panic!(r#"citation key not found in bibliography: "{key}""#);
+        };
+        let number = match self.cited.iter().position(|cited| cited == key) {
+            Some(i) => i + 1,
+            None => {
+                self.cited.push(key.to_owned());
+                self.cited.len()
+            }
+        };
+        let label = match self.style {
+            CitationStyle::Numeric => number.to_string(),
+            CitationStyle::AuthorYear => escape(&entry.label()),
+        };
+        let locator = locator
+            .map(|locator| format!(", {}", escape(locator)))
+            .unwrap_or_default();
+        format!(
+            r##"<a href="#{}">{label}</a>{locator}"##,
+            anchor(&self.scope, key)
+        )
+    }
+
+    /// The "References" section listing all cited entries, or an empty string
+    /// if nothing was cited.
+    pub fn references(&self) -> String {
+        if self.cited.is_empty() {
+            return String::new();
+        }
+        let mut cited: Vec<_> = self
+            .cited
+            .iter()
+            .map(|key| (key, &self.bibliography.entries[key]))
+            .collect();
+        let list = match self.style {
+            CitationStyle::Numeric => "ol",
+            CitationStyle::AuthorYear => {
+                cited.sort_by_key(|(_, entry)| entry.label());
+                "ul"
+            }
+        };
+        let mut html = format!("\n\n# References\n\n<{list} class=\"docext-references\">\n");
+        for (key, entry) in cited {
+            html.push_str(&format!(
+                "<li id=\"{}\">{}</li>\n",
+                anchor(&self.scope, key),
+                entry.reference()
+            ));
+        }
+        html.push_str(&format!("</{list}>\n"));
+        html
+    }
+}
+
+/// The HTML id of the reference with the given key.
+fn anchor(scope: &str, key: &str) -> String {
+    let key: String = key
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("docext-ref-{scope}-{key}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BIB: &str = r#"
+        @comment{ignored}
+        @book{golub2013,
+            author = {Golub, Gene H. and Charles F. {Van Loan}},
+            title = {Matrix Computations},
+            publisher = "Johns Hopkins University Press",
+            edition = 4,
+            year = 2013,
+        }
+        @article{knuth1984,
+            author = "Donald E. Knuth",
+            title = {Literate Programming},
+            journal = {The Computer Journal},
+            volume = {27}, number = {2},
+            pages = {97--111},
+            year = {1984},
+            doi = {10.1093/comjnl/27.2.97}
+        }
+        @misc{web,
+            title = {Web},
+            url = {https://example.com/a\_b?q="x"&y=--1},
+        }
+        @misc{script,
+            title = {Script},
+            url = {javascript:alert(1)},
+        }
+    "#;
+
+    fn citations(style: CitationStyle) -> Citations {
+        Citations::new(Bibliography::parse(BIB).unwrap(), style, "s")
+    }
+
+    /// Parses fields in all of the supported value formats.
+    #[test]
+    fn parse() {
+        let bib = Bibliography::parse(BIB).unwrap();
+        assert_eq!(bib.entries.len(), 4);
+        let golub = &bib.entries["golub2013"];
+        assert_eq!(golub.fields["edition"], "4");
+        assert_eq!(golub.fields["publisher"], "Johns Hopkins University Press");
+        assert_eq!(golub.label(), "Golub and Van Loan, 2013");
+        let knuth = &bib.entries["knuth1984"];
+        assert_eq!(knuth.label(), "Knuth, 1984");
+        assert_eq!(
+            knuth.reference(),
+            "Donald E. Knuth. Literate Programming. <em>The Computer Journal</em>, 27(2), pp. \
+             97–111, 1984. <a href=\"https://doi.org/10.1093/comjnl/27.2.97\">doi:10.1093/comjnl/27.2.97</a>"
+        );
+    }
+
+    /// Escapes links and only allows web links.
+    #[test]
+    fn links() {
+        let bib = Bibliography::parse(BIB).unwrap();
+        assert_eq!(
+            bib.entries["web"].reference(),
+            "Web. <a href=\"https://example.com/a_b?q=&quot;x&quot;&amp;y=--1\">\
+             https://example.com/a_b?q=&quot;x&quot;&amp;y=--1</a>"
+        );
+        assert_eq!(bib.entries["script"].reference(), "Script.");
+    }
+
+    /// Numbers citations in order of first appearance.
+    #[test]
+    fn numeric() {
+        let mut citations = citations(CitationStyle::Numeric);
+        assert_eq!(
+            citations.replace("See [@knuth1984] and [@golub2013, p. 42; @knuth1984]."),
+            "See <span class=\"docext-cite\">&#91;<a \
+             href=\"#docext-ref-s-knuth1984\">1</a>&#93;</span> and <span \
+             class=\"docext-cite\">&#91;<a href=\"#docext-ref-s-golub2013\">2</a>, p. 42; <a \
+             href=\"#docext-ref-s-knuth1984\">1</a>&#93;</span>."
+        );
+        let references = citations.references();
+        assert!(references.find("knuth1984").unwrap() < references.find("golub2013").unwrap());
+    }
+
+    /// Escapes locators.
+    #[test]
+    fn locator() {
+        let mut citations = citations(CitationStyle::Numeric);
+        assert_eq!(
+            citations.replace("[@knuth1984, <b>p. 1</b>]"),
+            "<span class=\"docext-cite\">&#91;<a href=\"#docext-ref-s-knuth1984\">1</a>, \
+             &lt;b&gt;p. 1&lt;/b&gt;&#93;</span>"
+        );
+    }
+
+    /// Renders author-year labels.
+    #[test]
+    fn author_year() {
+        let mut citations = citations(CitationStyle::AuthorYear);
+        assert_eq!(
+            citations.replace("[@golub2013]"),
+            "<span class=\"docext-cite\">(<a href=\"#docext-ref-s-golub2013\">Golub and Van Loan, \
+             2013</a>)</span>"
+        );
+    }
+
+    /// Leaves links and other bracketed text unchanged.
+    #[test]
+    fn not_citations() {
+        let mut citations = citations(CitationStyle::Numeric);
+        for text in [
+            "[@golub2013](url)",
+            "[@golub2013][ref]",
+            "[hello]",
+            "[@ foo]",
+        ] {
+            assert_eq!(citations.replace(text), text);
+        }
+        assert!(citations.references().is_empty());
+    }
+
+    /// Errors on missing keys.
+    #[test]
+    #[should_panic(expected = "citation key not found in bibliography: \"missing\"")]
+    fn missing_key() {
+        citations(CitationStyle::Numeric).replace("[@missing]");
+    }
+}
@@ -0,0 +1,245 @@
+use {
+    crate::config::{self, Budget, Budgets, Policy},
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        sync::Mutex,
+    },
+};
+
+/// Number of assets listed when a budget is exceeded.
+const OFFENDERS: usize = 5;
+
+/// Assets embedded into each item of each crate compiled by this process,
+/// keyed by crate and then by the location of the item. Keying by location
+/// keeps the totals correct when an item is expanded more than once.
+static CRATES: Mutex<BTreeMap<String, Crate>> = Mutex::new(BTreeMap::new());
+
+#[derive(Default)]
+struct Crate {
+    items: BTreeMap<String, Usage>,
+    /// Whether a warning about the crate budget was already printed.
+    warned: bool,
+}
+
+/// Check the size of a single embedded file against the per-file budget, which
+/// is configured as `image`. The kind, such as `"image"`, names the file in
+/// the error.
+pub fn check_file(budgets: &Budgets, kind: &str, name: &str, size: u64) {
+    check(
+        budgets.image,
+        format!(
+            r#"{kind} file too large: "{name}" is {}, exceeding the per-file budget of {}"#,
+            format_size(size),
+            format_size(budgets.image.map_or(0, |budget| budget.size)),
+        ),
+        size,
+        &[],
+    );
+}
+
+/// The assets embedded into a single item.
+#[derive(Debug, Default)]
+pub struct Usage {
+    assets: Vec<(String, u64)>,
+    /// Names of the assets which are stored in the shared asset store, see
+    /// [`crate::asset::share`].
+    shared: BTreeSet<String>,
+}
+
+impl Usage {
+    /// Record an asset embedded into the item.
+    pub fn add(&mut self, name: &str, size: u64) {
+        self.assets.push((name.to_owned(), size));
+    }
+
+    /// Record an asset stored in the shared asset store, which only counts
+    /// once towards the crate budget, however many items use it.
+    pub fn add_shared(&mut self, name: &str, size: u64) {
+        self.add(name, size);
+        self.shared.insert(name.to_owned());
+    }
+
+    /// Check the per-item and per-crate budgets once all assets of the item
+    /// have been recorded. The item is identified by its location, if it is
+    /// known, and only counts towards the crate budget then.
+    pub fn finish(self, budgets: &Budgets, location: Option<String>) {
+        let total = self.assets.iter().map(|(_, size)| size).sum();
+        let at = location
+            .as_ref()
+            .map_or(String::new(), |location| format!(" at {location}"));
+        check(
+            budgets.item,
+            format!(
+                "embedded files too large in the item{at}: {} in total, exceeding the per-item \
+                 budget of {}",
+                format_size(total),
+                format_size(budgets.item.map_or(0, |budget| budget.size)),
+            ),
+            total,
+            &self.assets,
+        );
+
+        let Some(location) = location else {
+            return;
+        };
+        let mut crates = CRATES.lock().unwrap_or_else(|err| err.into_inner());
+        let krate = crates.entry(config::crate_key()).or_default();
+        krate.items.insert(location, self);
+
+        // The same file can be embedded into many items, which adds up, unless
+        // it is shared.
+        let mut assets: BTreeMap<&str, u64> = BTreeMap::new();
+        let mut shared: BTreeMap<&str, u64> = BTreeMap::new();
+        for usage in krate.items.values() {
+            for (name, size) in &usage.assets {
+                if usage.shared.contains(name) {
+                    shared.insert(name, *size);
+                } else {
+                    *assets.entry(name).or_default() += size;
+                }
+            }
+        }
+        for (name, size) in shared {
+            *assets.entry(name).or_default() += size;
+        }
+        let assets: Vec<(String, u64)> = assets
+            .into_iter()
+            .map(|(name, size)| (name.to_owned(), size))
+            .collect();
+        let total = assets.iter().map(|(_, size)| size).sum();
+        if krate.warned
+            && budgets
+                .krate
+                .is_some_and(|budget| budget.policy == Policy::Warn)
+        {
+            return;
+        }
+        let exceeded = check(
+            budgets.krate,
+            format!(
+                "embedded files too large: {} in total, exceeding the per-crate budget of {}",
+                format_size(total),
+                format_size(budgets.krate.map_or(0, |budget| budget.size)),
+            ),
+            total,
+            &assets,
+        );
+        krate.warned |= exceeded;
+    }
+}
+
+/// Check a size against a budget, listing the largest assets if it is
+/// exceeded. Return whether the budget was exceeded.
+fn check(budget: Option<Budget>, message: String, size: u64, assets: &[(String, u64)]) -> bool {
+    let Some(budget) = budget else {
+        return false;
+    };
+    if size <= budget.size {
+        return false;
+    }
+    let message = format!("{message}{}", report(assets));
+    match budget.policy {
+        Policy::Warn => eprintln!("warning: {message}"),
+        Policy::Error => panic!("{message}"),
+    }
+    true
+}
+
+/// List the largest assets, biggest first.
+fn report(assets: &[(String, u64)]) -> String {
+    if assets.is_empty() {
+        return String::new();
+    }
+    let mut assets = assets.to_vec();
+    assets.sort_by(|(a_name, a_size), (b_name, b_size)| {
+        b_size.cmp(a_size).then_with(|| a_name.cmp(b_name))
+    });
+    let mut report = String::from("\nlargest files:");
+    for (name, size) in assets.iter().take(OFFENDERS) {
+        report.push_str(&format!("\n  {:>9}  {name}", format_size(*size)));
+    }
+    if assets.len() > OFFENDERS {
+        report.push_str(&format!("\n  and {} more", assets.len() - OFFENDERS));
+    }
+    report
+}
+
+/// Format a size in bytes for humans, such as "1.5MB".
+fn format_size(size: u64) -> String {
+    match size {
+        0..1024 => format!("{size}B"),
+        1024..0x100000 => format!("{}KB", decimal(size as f64 / 1024.0)),
+        _ => format!("{}MB", decimal(size as f64 / (1024.0 * 1024.0))),
+    }
+}
+
+/// Format a number with one decimal, dropping it if it is zero.
+fn decimal(number: f64) -> String {
+    let number = format!("{number:.1}");
+    number.trim_end_matches(".0").to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn budgets(item: Policy) -> Budgets {
+        Budgets {
+            item: Some(Budget {
+                size: 1000,
+                policy: item,
+            }),
+            ..Budgets::default()
+        }
+    }
+
+    /// Lists the largest files first when the item budget is exceeded.
+    #[test]
+    #[should_panic(expected = "largest files:\n       900B  b.png\n       200B  a.png")]
+    fn item() {
+        let mut usage = Usage::default();
+        usage.add("a.png", 200);
+        usage.add("b.png", 900);
+        usage.finish(&budgets(Policy::Error), None);
+    }
+
+    /// Continues when the policy is to warn.
+    #[test]
+    fn warn() {
+        let mut usage = Usage::default();
+        usage.add("a.png", 2000);
+        usage.finish(&budgets(Policy::Warn), None);
+    }
+
+    /// Counts shared assets once towards the crate budget.
+    #[test]
+    fn shared() {
+        let budgets = Budgets {
+            krate: Some(Budget {
+                size: 1000,
+                policy: Policy::Error,
+            }),
+            ..Budgets::default()
+        };
+        for location in ["shared.rs:1:1", "shared.rs:2:1"] {
+            let mut usage = Usage::default();
+            usage.add_shared("a.png", 600);
+            usage.finish(&budgets, Some(location.to_owned()));
+        }
+    }
+
+    /// Rejects large images by default.
+    #[test]
+    #[should_panic(expected = r#"image file too large: "a.png" is 1.5MB"#)]
+    fn image() {
+        check_file(&Budgets::default(), "image", "a.png", 1536 * 1024);
+    }
+
+    /// Formats sizes with sensible units.
+    #[test]
+    fn sizes() {
+        assert_eq!(format_size(10), "10B");
+        assert_eq!(format_size(1536), "1.5KB");
+        assert_eq!(format_size(1024 * 1024), "1MB");
+    }
+}
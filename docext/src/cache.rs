@@ -0,0 +1,241 @@
+use {
+    crate::{asset, config::ImageConfig},
+    std::{
+        env,
+        ffi::OsString,
+        fs,
+        path::{Path, PathBuf},
+        process,
+        sync::OnceLock,
+        time::{Duration, SystemTime},
+    },
+};
+
+/// Entries which haven't been used for this long are removed.
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Version of the cached output, which is part of the keys. It must be bumped
+/// whenever processing or sanitizing produces different output for the same
+/// input, so that entries produced by the old code aren't used.
+const FORMAT: u32 = 1;
+
+/// Cache of processed and sanitized images, stored in `target/docext-cache`,
+/// so that repeated expansions, such as those of incremental builds, can reuse
+/// them. Entries are keyed by the content of the file, the options it was
+/// processed with and the version of the output, see [`FORMAT`], so changed
+/// files, options and code never hit stale entries.
+///
+/// The cache is an optimization only, so errors are ignored.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Open the cache of the target directory the crate is being compiled
+    /// into, pruning entries which haven't been used for a while. Return `None`
+    /// if the target directory is unknown, such as when the macro is expanded
+    /// by an IDE.
+    pub fn open() -> Option<&'static Self> {
+        static CACHE: OnceLock<Option<Cache>> = OnceLock::new();
+        CACHE
+            .get_or_init(|| {
+                let cache = Self { dir: dir()? };
+                cache.prune(SystemTime::now());
+                Some(cache)
+            })
+            .as_ref()
+    }
+
+    /// Get the MIME type and data of an entry.
+    pub fn get(&self, key: &Key) -> Option<(String, Vec<u8>)> {
+        let path = self.dir.join(&key.name);
+        let entry = fs::read(&path).ok()?;
+        let Some((input, mime, data)) = parse(&entry) else {
+            // The entry is corrupt, for example because it was written by an
+            // incompatible version.
+            let _ = fs::remove_file(&path);
+            return None;
+        };
+        if input != key.input {
+            // The key collides with the key of another input.
+            return None;
+        }
+        // Keep used entries from being pruned.
+        let _ = fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some((mime.to_owned(), data.to_owned()))
+    }
+
+    /// Store an entry. Concurrent compilations can store the same entry, so it
+    /// is written to a temporary file first and then moved into place.
+    pub fn put(&self, key: &Key, mime: &str, data: &[u8]) {
+        let tmp = self.dir.join(format!("{}.{}.tmp", key.name, process::id()));
+        let mut entry = format!("{}\n{mime}\n", key.input).into_bytes();
+        entry.extend_from_slice(data);
+        let result = fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(&tmp, entry))
+            .and_then(|()| fs::rename(&tmp, self.dir.join(&key.name)));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+
+    /// Remove entries, including leftover temporary files, which haven't been
+    /// used since `MAX_AGE` before `now`.
+    fn prune(&self, now: SystemTime) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let stale = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| now.duration_since(modified).is_ok_and(|age| age > MAX_AGE));
+            if stale {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+/// The key of an entry.
+pub struct Key {
+    /// Name of the entry file.
+    name: String,
+    /// Length and hash of the input, which are stored in the entry, so that
+    /// collisions of the name are detected.
+    input: String,
+}
+
+/// The key of the entry for a file with the given content and extension,
+/// processed with the given options. Options which don't affect processing are
+/// left out, so that changing them keeps the entries.
+pub fn key(path: &Path, data: &[u8], config: &ImageConfig) -> Key {
+    let ext = path.extension().unwrap_or_default().to_string_lossy();
+    let ImageConfig {
+        max_width,
+        recompress,
+        jpeg_quality,
+        convert,
+        ..
+    } = config;
+    let input = format!("{}:{}", data.len(), asset::hash(data));
+    let name = asset::hash(
+        format!(
+            "{input}:{ext}:{max_width:?}:{recompress}:{jpeg_quality}:{convert:?}:{}:{FORMAT}",
+            env!("CARGO_PKG_VERSION")
+        )
+        .as_bytes(),
+    );
+    Key { name, input }
+}
+
+/// The cache directory inside of the target directory.
+fn dir() -> Option<PathBuf> {
+    Some(target_dir()?.join("docext-cache"))
+}
+
+/// The target directory the crate is being compiled into by cargo, or `None`
+/// if the macro is expanded outside of cargo, such as by an IDE. Cargo doesn't
+/// tell the compiler where the target directory is, so it is found from the
+/// output directory of the compiler, or from the dependency directory for
+/// rustdoc, as the closest ancestor with the `CACHEDIR.TAG` file which cargo
+/// creates.
+pub fn target_dir() -> Option<PathBuf> {
+    let args: Vec<OsString> = env::args_os().collect();
+    let out = args.windows(2).find_map(|args| match args[0].to_str()? {
+        "--out-dir" => Some(PathBuf::from(&args[1])),
+        "-L" => Some(PathBuf::from(
+            args[1].to_str()?.strip_prefix("dependency=")?,
+        )),
+        _ => None,
+    })?;
+    out.ancestors()
+        .find(|dir| dir.join("CACHEDIR.TAG").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Split an entry into the length and hash of its input, its MIME type and its
+/// data.
+fn parse(entry: &[u8]) -> Option<(&str, &str, &[u8])> {
+    let mut parts = entry.splitn(3, |&byte| byte == b'\n');
+    let input = std::str::from_utf8(parts.next()?).ok()?;
+    let mime = std::str::from_utf8(parts.next()?).ok()?;
+    Some((input, mime, parts.next()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cache(name: &str) -> Cache {
+        let dir = env::temp_dir().join(format!("docext-cache-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Cache { dir }
+    }
+
+    fn entry(name: &str, input: &[u8]) -> Key {
+        Key {
+            name: name.to_owned(),
+            ..key(Path::new("a.png"), input, &ImageConfig::default())
+        }
+    }
+
+    /// Stores and retrieves entries.
+    #[test]
+    fn entries() {
+        let cache = cache("entries");
+        let a = entry("a", b"a");
+        assert_eq!(cache.get(&a), None);
+        cache.put(&a, "image/png", b"\x89PNG\n");
+        assert_eq!(
+            cache.get(&a),
+            Some(("image/png".to_owned(), b"\x89PNG\n".to_vec()))
+        );
+        // Entries of other inputs with the same name aren't used.
+        assert_eq!(cache.get(&entry("a", b"b")), None);
+        // Corrupt entries are removed.
+        fs::write(cache.dir.join("b"), b"no newline").unwrap();
+        assert_eq!(cache.get(&entry("b", b"b")), None);
+        assert!(!cache.dir.join("b").exists());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    /// Removes entries which haven't been used for a while.
+    #[test]
+    fn prune() {
+        let cache = cache("prune");
+        cache.put(&entry("a", b"a"), "image/png", b"a");
+        cache.prune(SystemTime::now());
+        assert!(cache.dir.join("a").exists());
+        cache.prune(SystemTime::now() + MAX_AGE * 2);
+        assert!(!cache.dir.join("a").exists());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    /// Keys depend on the content, the extension and the processing options.
+    #[test]
+    fn keys() {
+        let config = ImageConfig::default();
+        let key = key(Path::new("a.png"), b"a", &config).name;
+        assert_eq!(key, self::key(Path::new("b/c.png"), b"a", &config).name);
+        assert_ne!(key, self::key(Path::new("a.png"), b"b", &config).name);
+        assert_ne!(key, self::key(Path::new("a.jpg"), b"a", &config).name);
+        let config = ImageConfig {
+            max_width: Some(100),
+            ..config
+        };
+        assert_ne!(key, self::key(Path::new("a.png"), b"a", &config).name);
+        let other = ImageConfig {
+            require_alt: true,
+            data_urls: true,
+            ..config
+        };
+        assert_eq!(
+            self::key(Path::new("a.png"), b"a", &config).name,
+            self::key(Path::new("a.png"), b"a", &other).name
+        );
+    }
+}
@@ -0,0 +1,471 @@
+use {
+    crate::attachment,
+    std::{env, fs, ops::RangeInclusive, path::PathBuf},
+};
+
+/// Crate-wide configuration, read from the `[package.metadata.docext]` table in
+/// the `Cargo.toml` of the crate being documented.
+///
+/// ```toml
+/// [package.metadata.docext]
+/// bibliography = "refs.bib"
+/// citation-style = "author-year"
+/// search-paths = ["../assets"]
+/// shared-assets = true
+/// attachments = ["toml", "csv"]
+/// check-links = false
+///
+/// [package.metadata.docext.images]
+/// max-width = 800
+/// recompress = true
+/// inline-svg = true
+///
+/// [package.metadata.docext.budget]
+/// image = "512KB"
+/// item = { size = "2MB", policy = "warn" }
+/// crate = "8MB"
+/// ```
+#[derive(Debug)]
+pub struct Config {
+    /// Path to the BibTeX file used to resolve citations.
+    pub bibliography: Option<PathBuf>,
+    /// How citations and references are rendered.
+    pub citation_style: CitationStyle,
+    /// How images are processed before they are embedded.
+    pub images: ImageConfig,
+    /// Limits on the size of embedded assets.
+    pub budgets: Budgets,
+    /// Additional directories which files are searched in, after the crate
+    /// root.
+    pub search_paths: Vec<PathBuf>,
+    /// Store each embedded file once, in the documentation output, instead of
+    /// in every item using it.
+    pub shared_assets: bool,
+    /// Extensions of the linked files which are embedded, such as `"csv"`.
+    /// `None` embeds the default [`attachment::EXTENSIONS`].
+    pub attachments: Option<Vec<String>>,
+    /// Error on links to local files which don't exist, see
+    /// [`attachment::is_checked`].
+    pub check_links: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bibliography: None,
+            citation_style: CitationStyle::default(),
+            images: ImageConfig::default(),
+            budgets: Budgets::default(),
+            search_paths: Vec::new(),
+            shared_assets: false,
+            attachments: None,
+            check_links: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CitationStyle {
+    /// Citations are rendered as `[1]`, references are numbered in order of
+    /// citation.
+    #[default]
+    Numeric,
+    /// Citations are rendered as `(Golub and Van Loan, 2013)`, references are
+    /// sorted by author.
+    AuthorYear,
+}
+
+/// Image processing options. Processing requires the `processing` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageConfig {
+    /// Downscale images wider than this many pixels.
+    pub max_width: Option<u32>,
+    /// Re-encode PNG and JPEG images, keeping the result if it is smaller.
+    pub recompress: bool,
+    /// Quality used when re-encoding JPEG images, from 1 to 100.
+    pub jpeg_quality: u8,
+    /// Format that images which can't be shown inline by browsers, such as
+    /// TIFF and BMP, are converted to.
+    pub convert: Option<ConvertFormat>,
+    /// Insert SVG images into the page as elements instead of images, so that
+    /// `currentColor` and CSS variables follow the rustdoc theme.
+    pub inline_svg: bool,
+    /// Error on images without alt text.
+    pub require_alt: bool,
+    /// Link markdown images to their data directly instead of loading them
+    /// with a script, so that they are shown without JavaScript. Dark theme
+    /// variants and inline SVG require the script, so they are not supported.
+    pub data_urls: bool,
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        Self {
+            max_width: None,
+            recompress: false,
+            jpeg_quality: 85,
+            convert: None,
+            inline_svg: false,
+            require_alt: false,
+            data_urls: false,
+        }
+    }
+}
+
+/// Limits on the total size of the assets embedded into the documentation.
+/// Large doc comments slow down the compiler and can even crash it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budgets {
+    /// Limit on the size of a single embedded file.
+    pub image: Option<Budget>,
+    /// Limit on the total size of the files embedded into a single item.
+    pub item: Option<Budget>,
+    /// Limit on the total size of the files embedded into the crate. It is only
+    /// checked when the crate is compiled by cargo, since IDEs expand items
+    /// again whenever they move, which would count their files again.
+    pub krate: Option<Budget>,
+}
+
+impl Default for Budgets {
+    fn default() -> Self {
+        Self {
+            image: Some(Budget {
+                size: 1024 * 1024,
+                policy: Policy::Error,
+            }),
+            item: None,
+            krate: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    /// Maximum size in bytes.
+    pub size: u64,
+    /// What happens when the budget is exceeded.
+    pub policy: Policy,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Print a warning and continue. Proc macros can't emit compiler warnings
+    /// on stable, so it is printed to the standard error of the compiler,
+    /// which cargo doesn't show for dependencies.
+    Warn,
+    /// Fail the compilation.
+    #[default]
+    Error,
+}
+
+impl Config {
+    /// Whether linked files with the given extension are embedded.
+    pub fn is_attachment(&self, ext: &str) -> bool {
+        let ext = ext.to_ascii_lowercase();
+        match &self.attachments {
+            Some(extensions) => extensions.contains(&ext),
+            None => attachment::EXTENSIONS.contains(&ext.as_str()),
+        }
+    }
+}
+
+impl ImageConfig {
+    /// Whether any processing is enabled.
+    pub fn enabled(&self) -> bool {
+        self.max_width.is_some() || self.recompress || self.convert.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvertFormat {
+    Png,
+    WebP,
+}
+
+impl Config {
+    /// Load the configuration of the crate currently being compiled.
+    pub fn load() -> Self {
+        let path = manifest_dir().join("Cargo.toml");
+        let manifest = fs::read_to_string(&path).unwrap_or_else(|_| {
+            panic!(
+                r#"failed to read crate manifest: "{}""#,
+                path.to_string_lossy()
+            )
+        });
+        let manifest: toml::Table = manifest.parse().unwrap_or_else(|err| {
+            panic!(
+                r#"failed to parse crate manifest: "{}": {err}"#,
+                path.to_string_lossy()
+            )
+        });
+
+        let mut config = Self::default();
+        let Some(table) = manifest
+            .get("package")
+            .and_then(|package| package.get("metadata"))
+            .and_then(|metadata| metadata.get("docext"))
+        else {
+            return config;
+        };
+        let table = table
+            .as_table()
+            .unwrap_or_else(|| panic!("[package.metadata.docext] must be a table"));
+
+        for (key, value) in table {
+            match key.as_str() {
+                "bibliography" => {
+                    config.bibliography = Some(manifest_dir().join(string(key, value)))
+                }
+                "citation-style" => {
+                    config.citation_style = match string(key, value) {
+                        "numeric" => CitationStyle::Numeric,
+                        "author-year" => CitationStyle::AuthorYear,
+                        other => panic!(
+                            r#"unknown citation style: "{other}", expected "numeric" or "author-year""#
+                        ),
+                    }
+                }
+                "images" => config.images = ImageConfig::parse(value),
+                "budget" => config.budgets = Budgets::parse(value),
+                "search-paths" => {
+                    config.search_paths = value
+                        .as_array()
+                        .unwrap_or_else(|| {
+                            panic!(r#"[package.metadata.docext] "{key}" must be an array"#)
+                        })
+                        .iter()
+                        .map(|path| manifest_dir().join(string(key, path)))
+                        .collect()
+                }
+                "shared-assets" => config.shared_assets = boolean(key, value),
+                "check-links" => config.check_links = boolean(key, value),
+                "attachments" => {
+                    config.attachments = Some(
+                        value
+                            .as_array()
+                            .unwrap_or_else(|| {
+                                panic!(r#"[package.metadata.docext] "{key}" must be an array"#)
+                            })
+                            .iter()
+                            .map(|ext| {
+                                string(key, ext)
+                                    .trim_start_matches('.')
+                                    .to_ascii_lowercase()
+                            })
+                            .collect(),
+                    )
+                }
+                _ => panic!(r#"unknown key in [package.metadata.docext]: "{key}""#),
+            }
+        }
+        config
+    }
+}
+
+impl ImageConfig {
+    fn parse(value: &toml::Value) -> Self {
+        let table = value
+            .as_table()
+            .unwrap_or_else(|| panic!("[package.metadata.docext.images] must be a table"));
+        let mut config = Self::default();
+        for (key, value) in table {
+            let key = format!("images.{key}");
+            match key.as_str() {
+                "images.max-width" => {
+                    config.max_width = Some(integer(&key, value, 1..=u32::MAX as i64) as u32)
+                }
+                "images.recompress" => config.recompress = boolean(&key, value),
+                "images.jpeg-quality" => config.jpeg_quality = integer(&key, value, 1..=100) as u8,
+                "images.inline-svg" => config.inline_svg = boolean(&key, value),
+                "images.require-alt" => config.require_alt = boolean(&key, value),
+                "images.data-urls" => config.data_urls = boolean(&key, value),
+                "images.convert" => {
+                    config.convert = Some(match string(&key, value) {
+                        "png" => ConvertFormat::Png,
+                        "webp" => ConvertFormat::WebP,
+                        other => {
+                            panic!(r#"unknown image format: "{other}", expected "png" or "webp""#)
+                        }
+                    })
+                }
+                _ => panic!(r#"unknown key in [package.metadata.docext]: "{key}""#),
+            }
+        }
+        config
+    }
+}
+
+impl Budgets {
+    fn parse(value: &toml::Value) -> Self {
+        let table = value
+            .as_table()
+            .unwrap_or_else(|| panic!("[package.metadata.docext.budget] must be a table"));
+        let mut budgets = Self::default();
+        for (key, value) in table {
+            let budget = Budget::parse(&format!("budget.{key}"), value);
+            match key.as_str() {
+                "image" => budgets.image = budget,
+                "item" => budgets.item = budget,
+                "crate" => budgets.krate = budget,
+                _ => panic!(r#"unknown key in [package.metadata.docext]: "budget.{key}""#),
+            }
+        }
+        budgets
+    }
+}
+
+impl Budget {
+    /// Parse a budget, given either as a size such as `"1MB"`, as a table such
+    /// as `{ size = "1MB", policy = "warn" }`, or as `false` to disable it.
+    fn parse(key: &str, value: &toml::Value) -> Option<Self> {
+        match value {
+            toml::Value::Boolean(false) => None,
+            toml::Value::Table(table) => {
+                let mut size = None;
+                let mut policy = Policy::default();
+                for (field, value) in table {
+                    let key = format!("{key}.{field}");
+                    match field.as_str() {
+                        "size" => size = Some(self::size(&key, value)),
+                        "policy" => {
+                            policy = match string(&key, value) {
+                                "warn" => Policy::Warn,
+                                "error" => Policy::Error,
+                                other => panic!(
+                                    r#"unknown budget policy: "{other}", expected "warn" or "error""#
+                                ),
+                            }
+                        }
+                        _ => panic!(r#"unknown key in [package.metadata.docext]: "{key}""#),
+                    }
+                }
+                let size = size.unwrap_or_else(|| {
+                    panic!(r#"[package.metadata.docext] "{key}" is missing a size"#)
+                });
+                Some(Self { size, policy })
+            }
+            value => Some(Self {
+                size: size(key, value),
+                policy: Policy::default(),
+            }),
+        }
+    }
+}
+
+/// The root directory of the crate currently being compiled.
+pub fn manifest_dir() -> PathBuf {
+    PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
+}
+
+/// A key identifying the crate currently being compiled, for state which is
+/// kept across the expansions of its items. A package can contain several
+/// crates, such as a library and binaries, which are told apart by name.
+pub fn crate_key() -> String {
+    format!(
+        "{}:{}",
+        env::var("CARGO_MANIFEST_DIR").unwrap_or_default(),
+        env::var("CARGO_CRATE_NAME").unwrap_or_default()
+    )
+}
+
+/// Get a boolean value from the config, or error if it has a different type.
+fn boolean(key: &str, value: &toml::Value) -> bool {
+    value
+        .as_bool()
+        .unwrap_or_else(|| panic!(r#"[package.metadata.docext] "{key}" must be a boolean"#))
+}
+
+/// Get an integer value from the config, or error if it has a different type
+/// or is out of range.
+fn integer(key: &str, value: &toml::Value, range: RangeInclusive<i64>) -> i64 {
+    match value.as_integer() {
+        Some(value) if range.contains(&value) => value,
+        _ => panic!(
+            r#"[package.metadata.docext] "{key}" must be an integer between {} and {}"#,
+            range.start(),
+            range.end()
+        ),
+    }
+}
+
+/// Get a size in bytes from the config, given either as an integer or as a
+/// string with a unit such as `"512KB"` or `"1.5MB"`. Units are powers of 1024.
+fn size(key: &str, value: &toml::Value) -> u64 {
+    let invalid = || -> ! {
+        panic!(
+            r#"[package.metadata.docext] "{key}" must be a size in bytes, or a string such as "512KB" or "2MB""#
+        )
+    };
+    match value {
+        toml::Value::Integer(size) => u64::try_from(*size).unwrap_or_else(|_| invalid()),
+        toml::Value::String(size) => parse_size(size).unwrap_or_else(|| invalid()),
+        _ => invalid(),
+    }
+}
+
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let unit = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return None,
+    };
+    Some((number * unit as f64).round() as u64)
+}
+
+/// Get a string value from the config, or error if it has a different type.
+fn string<'a>(key: &str, value: &'a toml::Value) -> &'a str {
+    value
+        .as_str()
+        .unwrap_or_else(|| panic!(r#"[package.metadata.docext] "{key}" must be a string"#))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Parses sizes with and without units.
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("2048"), Some(2048));
+        assert_eq!(parse_size("512KB"), Some(512 * 1024));
+        assert_eq!(parse_size("1.5 MiB"), Some(1536 * 1024));
+        assert_eq!(parse_size("1gb"), Some(1 << 30));
+        assert_eq!(parse_size("MB"), None);
+        assert_eq!(parse_size("1 parsec"), None);
+    }
+
+    /// Parses budgets in all supported forms.
+    #[test]
+    fn budgets() {
+        let value: toml::Value = toml::from_str(
+            r#"
+            image = false
+            item = { size = "2MB", policy = "warn" }
+            crate = 1000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            Budgets::parse(&value),
+            Budgets {
+                image: None,
+                item: Some(Budget {
+                    size: 2 << 20,
+                    policy: Policy::Warn
+                }),
+                krate: Some(Budget {
+                    size: 1000,
+                    policy: Policy::Error
+                }),
+            }
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use {
+    crate::{
+        attrs::{escape, Attrs},
+        splice,
+    },
+    pulldown_cmark::{Event, Options, Parser, Tag, TagEnd},
+    regex::Regex,
+    std::{collections::HashMap, ops::Range},
+};
+
+/// Prefix of the alt text which turns an image into a figure, such as
+/// `![Figure: The data flow](img/flow.svg){#fig:flow}`.
+pub const PREFIX: &str = "Figure:";
+
+/// Number the figures in the doc comment and replace references to them, such
+/// as `@fig:flow`, with links. The figures themselves are rendered along with
+/// the other images, see [`html`].
+///
+/// Figures are numbered per item. Each item is expanded on its own, in no
+/// particular order, so the numbers of figures in other items aren't known.
+/// References to them give the path of the item, as
+/// `[@fig:flow](path::to::Item)`, and are shown as "Figure" without a number,
+/// even if this item has a figure with the same label. Links without a path,
+/// such as `[@fig:flow](#)`, refer to the figures in this item.
+pub fn render(doc: &str, opts: Options) -> String {
+    if !doc.contains(PREFIX) && !doc.contains("@fig:") {
+        return doc.to_owned();
+    }
+
+    // Labels of the figures in this item, with their numbers.
+    let mut labels = HashMap::new();
+    // Spans which can't contain references.
+    let mut verbatim = Vec::new();
+    // Links whose text is a reference, with the link destination.
+    let mut links = Vec::new();
+    let mut figures = 0;
+    // The image currently being parsed, with its alt text.
+    let mut image: Option<(Range<usize>, String)> = None;
+    for (ev, range) in Parser::new_ext(doc, opts).into_offset_iter() {
+        match ev {
+            Event::Start(Tag::Image { .. }) => {
+                verbatim.push(range.clone());
+                image = Some((range, String::new()));
+            }
+            Event::Text(text) | Event::Code(text) if image.is_some() => {
+                image.as_mut().unwrap().1.push_str(&text);
+            }
+            Event::End(TagEnd::Image) => {
+                let Some((range, alt)) = image.take() else {
+                    continue;
+                };
+                if !alt.starts_with(PREFIX) {
+                    continue;
+                }
+                figures += 1;
+                if let Some(id) = Attrs::parse(&doc[range.end..]).and_then(|(attrs, _)| attrs.id) {
+                    if labels.insert(id.clone(), figures).is_some() {
+                        panic!(r#"duplicate figure label: "{id}""#);
+                    }
+                }
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                links.push((range.clone(), dest_url.into_string()));
+                verbatim.push(range);
+            }
+            Event::Start(Tag::CodeBlock(..))
+            | Event::Code(..)
+            | Event::Html(..)
+            | Event::InlineHtml(..) => verbatim.push(range),
+            _ => {}
+        }
+    }
+
+    let reference = Regex::new(r"@(?<label>fig:[\w\-]+)").unwrap();
+    let link = Regex::new(r"^\[@(?<label>fig:[\w\-]+)\]\(").unwrap();
+    let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+    for (range, dest) in links {
+        let Some(captures) = link.captures(&doc[range.clone()]) else {
+            continue;
+        };
+        let label = &captures["label"];
+        let dest = dest.split('#').next().unwrap();
+        let text = if dest.is_empty() {
+            format!("Figure {}", number(&labels, label))
+        } else {
+            "Figure".to_owned()
+        };
+        replacements.push((range, format!("[{text}]({dest}#{label})")));
+    }
+    for captures in reference.captures_iter(doc) {
+        let m = captures.get(0).unwrap();
+        if verbatim.iter().any(|section| section.contains(&m.start()))
+            || doc[..m.start()]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || c == '\\')
+        {
+            continue;
+        }
+        let label = &captures["label"];
+        let number = number(&labels, label);
+        replacements.push((m.range(), format!("[Figure {number}](#{label})")));
+    }
+
+    let mut result = splice(doc, replacements);
+    if figures > 0 {
+        result.push('\n');
+        result.push_str(STYLE);
+    }
+    result
+}
+
+/// The number of a figure in this item.
+fn number(labels: &HashMap<String, usize>, label: &str) -> usize {
+    let Some(number) = labels.get(label) else {
+        panic!(
+            r#"unknown figure: "@{label}", reference figures in other items as "[@{label}](path::to::Item)""#
+        );
+    };
+    *number
+}
+
+/// Render a figure containing the image tag, with a numbered caption.
+pub fn html(number: usize, id: Option<&str>, img: &str, caption: &str) -> String {
+    let id = id.map_or(String::new(), |id| format!(r#" id="{}""#, escape(id)));
+    format!(
+        r#"<figure class="docext-figure"{id}>{img}<figcaption>Figure {number}: {}</figcaption></figure>"#,
+        escape(caption.trim())
+    )
+}
+
+/// Styling of figures.
+const STYLE: &str = r#"<style>
+.docext-figure {
+    margin: 1em 0;
+    text-align: center;
+}
+.docext-figure figcaption {
+    font-style: italic;
+}
+</style>
+"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Replaces references to figures in the same item with links.
+    #[test]
+    fn references() {
+        let doc = render(
+            " See @fig:b and @fig:a.\n\n ![Figure: A](a.png){#fig:a}\n\n ![Figure: \
+             B](b.png){#fig:b}\n\n `@fig:a` and a@fig:a\n",
+            Options::empty(),
+        );
+        assert!(doc.starts_with(" See [Figure 2](#fig:b) and [Figure 1](#fig:a).\n"));
+        assert!(doc.contains(" `@fig:a` and a@fig:a\n"));
+    }
+
+    /// Links to figures in other items by their path, without a number, even if
+    /// this item has a figure with the same label.
+    #[test]
+    fn other_items() {
+        let doc = render(
+            " See [@fig:x](crate::Other::method), [@fig:a](crate::Test) or [@fig:a](#).\n\n \
+             ![Figure: A](a.png){#fig:a}\n",
+            Options::empty(),
+        );
+        assert!(doc.starts_with(
+            " See [Figure](crate::Other::method#fig:x), [Figure](crate::Test#fig:a) or [Figure \
+             1](#fig:a).\n"
+        ));
+    }
+
+    /// Errors on references to unknown figures.
+    #[test]
+    #[should_panic(
+        expected = r#"unknown figure: "@fig:missing", reference figures in other items"#
+    )]
+    fn unknown() {
+        render(" See @fig:missing.\n", Options::empty());
+    }
+
+    /// Errors on links without a path to figures which aren't in this item.
+    #[test]
+    #[should_panic(expected = r#"unknown figure: "@fig:missing""#)]
+    fn unknown_link() {
+        render(" See [@fig:missing](#).\n", Options::empty());
+    }
+
+    /// Renders figures with numbered captions.
+    #[test]
+    fn figure() {
+        assert_eq!(
+            html(2, Some("fig:a"), "<img src=\"a.png\">", " A < B"),
+            "<figure class=\"docext-figure\" id=\"fig:a\"><img src=\"a.png\"><figcaption>Figure \
+             2: A &lt; B</figcaption></figure>"
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use regex::{Captures, Regex};
+
+/// Turns scholarly identifiers in prose, such as `doi:10.1137/1.9781611971446`,
+/// `arXiv:2101.00001`, `ISBN:978-0-89871-361-9` and `OEIS:A000045`, into links.
+///
+/// Identifiers which don't validate, such as ISBNs with a wrong check digit,
+/// are left as they are, since they may just be prose which happens to look
+/// like an identifier.
+pub struct Identifiers {
+    identifier: Regex,
+    doi: Regex,
+    arxiv: Regex,
+    oeis: Regex,
+}
+
+impl Identifiers {
+    pub fn new() -> Self {
+        Self {
+            // Bare URLs are matched as well, so that identifiers inside of them are
+            // skipped. Identifiers must not follow characters which can be part of a
+            // path or a word.
+            identifier: Regex::new(
+                r"(?i)(?<url>\b[a-z][a-z0-9+.\-]*://[^\s<>]*)|(?<lead>^|[^\w/.:=?&#%@\-])(?<prefix>doi|arxiv|isbn|oeis):(?<id>[^\s<>]+)",
+            )
+            .unwrap(),
+            doi: Regex::new(r"^10\.\d{4,9}/\S+$").unwrap(),
+            // Identifiers since 2007 look like 2101.00001, older ones like math/0211159.
+            arxiv: Regex::new(r"^(?:\d{4}\.\d{4,5}|[a-z\-]+(?:\.[A-Z]{2})?/\d{7})(?:v\d+)?$")
+                .unwrap(),
+            oeis: Regex::new(r"^A\d{6}$").unwrap(),
+        }
+    }
+
+    /// Replace all identifiers in the text with links.
+    pub fn autolink(&self, text: &str) -> String {
+        if !text.contains(':') {
+            return text.to_owned();
+        }
+        self.identifier
+            .replace_all(text, |captures: &Captures| {
+                if captures.name("url").is_some() {
+                    return captures[0].to_owned();
+                }
+                let prefix = captures["prefix"].to_ascii_lowercase();
+                let id = &captures["id"];
+                // Identifiers other than DOIs end at the first character which can't be
+                // part of them, such as in "OEIS:A000045's".
+                let id = match prefix.as_str() {
+                    "doi" => id,
+                    _ => id
+                        .split(|c: char| !c.is_alphanumeric() && !matches!(c, '.' | '-' | '/'))
+                        .next()
+                        .unwrap(),
+                };
+                let id = trim_punctuation(id);
+                let link = match prefix.as_str() {
+                    "doi" => self.doi(id),
+                    "arxiv" => self.arxiv(id),
+                    "isbn" => isbn(id),
+                    "oeis" => self.oeis(id),
+                    _ => unreachable!(),
+                };
+                let Some(link) = link else {
+                    return captures[0].to_owned();
+                };
+                let rest = &captures["id"][id.len()..];
+                format!("{}{link}{rest}", &captures["lead"])
+            })
+            .into_owned()
+    }
+
+    fn doi(&self, id: &str) -> Option<String> {
+        self.doi.is_match(id).then(|| {
+            link(
+                &format!("https://doi.org/{}", encode(id)),
+                &format!("doi:{id}"),
+            )
+        })
+    }
+
+    fn arxiv(&self, id: &str) -> Option<String> {
+        self.arxiv.is_match(id).then(|| {
+            link(
+                &format!("https://arxiv.org/abs/{id}"),
+                &format!("arXiv:{id}"),
+            )
+        })
+    }
+
+    fn oeis(&self, id: &str) -> Option<String> {
+        self.oeis
+            .is_match(id)
+            .then(|| link(&format!("https://oeis.org/{id}"), &format!("OEIS {id}")))
+    }
+}
+
+/// Link an ISBN with 10 or 13 digits and a valid check digit.
+fn isbn(id: &str) -> Option<String> {
+    let digits: Vec<u32> = id
+        .chars()
+        .filter(|c| *c != '-')
+        .enumerate()
+        .map(|(i, c)| match c {
+            'X' | 'x' if i == 9 => Some(10),
+            c => c.to_digit(10),
+        })
+        .collect::<Option<_>>()?;
+    let valid = match digits.len() {
+        10 => {
+            digits
+                .iter()
+                .enumerate()
+                .map(|(i, d)| (10 - i as u32) * d)
+                .sum::<u32>()
+                % 11
+                == 0
+        }
+        13 => {
+            digits
+                .iter()
+                .enumerate()
+                .map(|(i, d)| if i % 2 == 0 { *d } else { 3 * d })
+                .sum::<u32>()
+                % 10
+                == 0
+        }
+        _ => false,
+    };
+    if !valid {
+        return None;
+    }
+    let digits: String = id.chars().filter(|c| *c != '-').collect();
+    Some(link(
+        &format!("https://openlibrary.org/isbn/{digits}"),
+        &format!("ISBN {id}"),
+    ))
+}
+
+fn link(url: &str, text: &str) -> String {
+    let text = text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    format!(r#"<a class="docext-id" href="{url}">{text}</a>"#)
+}
+
+/// Remove trailing sentence punctuation and possessives, which are most likely
+/// not part of the identifier. Closing parentheses and brackets are only
+/// removed if they are unbalanced, since DOIs can contain them.
+fn trim_punctuation(mut id: &str) -> &str {
+    loop {
+        let trimmed = id.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"', '*', '_']);
+        let trimmed = trimmed
+            .strip_suffix("'s")
+            .or_else(|| trimmed.strip_suffix("\u{2019}s"))
+            .unwrap_or(trimmed);
+        let trimmed = match trimmed.chars().last() {
+            Some(')') if trimmed.matches('(').count() < trimmed.matches(')').count() => {
+                &trimmed[..trimmed.len() - 1]
+            }
+            Some(']') if trimmed.matches('[').count() < trimmed.matches(']').count() => {
+                &trimmed[..trimmed.len() - 1]
+            }
+            _ => trimmed,
+        };
+        if trimmed == id {
+            return id;
+        }
+        id = trimmed;
+    }
+}
+
+/// Percent-encode the characters of an identifier that are not allowed in URLs.
+fn encode(id: &str) -> String {
+    let mut result = String::with_capacity(id.len());
+    for c in id.chars() {
+        match c {
+            '%' | '"' | '#' | '?' | '<' | '>' | '\\' | '^' | '`' | '{' | '|' | '}' => {
+                result.push_str(&format!("%{:02X}", c as u32))
+            }
+            '&' => result.push_str("&amp;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn autolink(text: &str) -> String {
+        Identifiers::new().autolink(text)
+    }
+
+    /// Links all kinds of identifiers with consistent formatting.
+    #[test]
+    fn links() {
+        assert_eq!(
+            autolink("See doi:10.1137/1.9781611971446."),
+            "See <a class=\"docext-id\" \
+             href=\"https://doi.org/10.1137/1.9781611971446\">doi:10.1137/1.9781611971446</a>."
+        );
+        assert_eq!(
+            autolink("(arxiv:2101.00001v2)"),
+            "(<a class=\"docext-id\" \
+             href=\"https://arxiv.org/abs/2101.00001v2\">arXiv:2101.00001v2</a>)"
+        );
+        assert_eq!(
+            autolink("arXiv:math/0211159"),
+            "<a class=\"docext-id\" href=\"https://arxiv.org/abs/math/0211159\">arXiv:math/0211159</a>"
+        );
+        assert_eq!(
+            autolink("ISBN:978-0-89871-361-9, "),
+            "<a class=\"docext-id\" href=\"https://openlibrary.org/isbn/9780898713619\">ISBN \
+             978-0-89871-361-9</a>, "
+        );
+        assert_eq!(
+            autolink("OEIS:A000045"),
+            "<a class=\"docext-id\" href=\"https://oeis.org/A000045\">OEIS A000045</a>"
+        );
+    }
+
+    /// Keeps balanced parentheses, which are valid in DOIs.
+    #[test]
+    fn doi_parentheses() {
+        assert!(autolink("doi:10.1016/0022-247X(77)90094-3)").contains(
+            "href=\"https://doi.org/10.1016/0022-247X(77)90094-3\">doi:10.1016/0022-247X(77)90094-3</a>)"
+        ));
+    }
+
+    /// Validates ISBN-10 check digits.
+    #[test]
+    fn isbn10() {
+        assert!(autolink("ISBN:0-306-40615-2").contains("openlibrary.org/isbn/0306406152"));
+        assert!(autolink("ISBN:0-8044-2957-X").contains("openlibrary.org/isbn/080442957X"));
+    }
+
+    /// Leaves text without identifiers unchanged.
+    #[test]
+    fn unchanged() {
+        for text in [
+            "hello",
+            "https://doi.org/10.1137/1",
+            "redoi:10.1/x",
+            "https://x.org/works/doi:10.1/x",
+            "x.org/works/doi:10.1/x",
+        ] {
+            assert_eq!(autolink(text), text);
+        }
+    }
+
+    /// Stops at characters which can't be part of the identifier.
+    #[test]
+    fn boundaries() {
+        assert_eq!(
+            autolink("OEIS:A000045's terms"),
+            "<a class=\"docext-id\" href=\"https://oeis.org/A000045\">OEIS A000045</a>'s terms"
+        );
+        assert!(autolink("doi:10.1137/1.9781611971446's").ends_with("</a>'s"));
+    }
+
+    /// Leaves text which doesn't validate unlinked.
+    #[test]
+    fn malformed() {
+        for text in [
+            "doi:10.11/x",
+            "ISBN:978-0-89871-361-8",
+            "ISBN:978-0-8987",
+            "ISBN: see below",
+            "ISBN:tbd",
+            "OEIS:A45",
+            "arXiv:soon",
+        ] {
+            assert_eq!(autolink(text), text);
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use {
+    crate::{
+        attrs::escape,
+        budget,
+        cache::{self, Cache},
+        config::{Budgets, ImageConfig},
+        resolve::Resolver,
+        svg,
+    },
+    regex::{Captures, Regex},
+    std::{fs, path::Path},
+};
+
+/// Load a local image to be embedded into the doc comment, processing it
+/// according to the config and checking it against the per-file budget. The
+/// name is the path used in the doc comment. Return the MIME type and the
+/// image data. Processed and sanitized images are cached, see [`Cache`].
+pub fn load(
+    path: &Path,
+    name: &str,
+    config: &ImageConfig,
+    budgets: &Budgets,
+) -> (&'static str, Vec<u8>) {
+    let metadata = fs::metadata(path).unwrap_or_else(|_| {
+        panic!(
+            r#"failed to stat image: "{}", is the file missing?"#,
+            path.to_string_lossy()
+        )
+    });
+    // Ensure that the file is not too large before reading it. If processing is
+    // enabled, the budget applies to the processed image instead.
+    if !config.enabled() {
+        budget::check_file(budgets, "image", name, metadata.len());
+    }
+
+    let data = fs::read(path).unwrap_or_else(|_| {
+        panic!(
+            r#"failed to read image: "{}", is the file missing?"#,
+            path.to_string_lossy()
+        )
+    });
+    // The data URL requires a MIME type.
+    let mime = mime(path, &data);
+    let svg = mime == "image/svg+xml";
+    // Only processed and sanitized images are cached, as loading other images
+    // is no slower than reading the cache.
+    let cache = (svg || config.enabled())
+        .then(Cache::open)
+        .flatten()
+        .map(|cache| (cache, cache::key(path, &data, config)));
+    if let Some((cache, key)) = &cache {
+        if let Some((mime, data)) = cache.get(key) {
+            if let Some(mime) = known_mime(&mime) {
+                if config.enabled() {
+                    budget::check_file(budgets, "image", name, data.len() as u64);
+                }
+                return (mime, data);
+            }
+        }
+    }
+
+    // SVG images are always sanitized, since they can contain scripts.
+    let data = if svg {
+        svg::sanitize(path, &data)
+    } else {
+        data
+    };
+    if !config.enabled() {
+        if let Some((cache, key)) = &cache {
+            cache.put(key, mime, &data);
+        }
+        return (mime, data);
+    }
+
+    #[cfg(not(feature = "processing"))]
+    panic!(
+        "image processing is configured in [package.metadata.docext.images], but the \
+         \"processing\" feature of docext is not enabled"
+    );
+
+    #[cfg(feature = "processing")]
+    {
+        let (mime, data) = crate::process::process(path, mime, data, config);
+        budget::check_file(budgets, "image", name, data.len() as u64);
+        if let Some((cache, key)) = &cache {
+            cache.put(key, mime, &data);
+        }
+        (mime, data)
+    }
+}
+
+/// Find the images, videos and audio files used by `<img>`, `<source>`,
+/// `<video>` and `<audio>` tags in HTML, in their `src` and `srcset`
+/// attributes.
+pub fn html_sources(html: &str) -> Vec<String> {
+    let mut sources = Vec::new();
+    map_html_sources(html, |src| {
+        if !src.is_empty() {
+            sources.push(src.to_owned());
+        }
+        src.to_owned()
+    });
+    sources
+}
+
+/// Replace the sources found by [`html_sources`] in HTML, leaving the rest of
+/// it unchanged.
+pub fn map_html_sources(html: &str, mut f: impl FnMut(&str) -> String) -> String {
+    let comment = Regex::new(r"(?s)<!--.*?-->").unwrap();
+    let tag = Regex::new(r"(?is)<(?:img|source|video|audio)\b[^>]*>").unwrap();
+    let attr = Regex::new(
+        r#"(?is)\s(?<name>src|srcset)\s*=\s*(?:"(?<dq>[^"]*)"|'(?<sq>[^']*)'|(?<uq>[^\s"'>]+))"#,
+    )
+    .unwrap();
+
+    let comments: Vec<_> = comment.find_iter(html).map(|m| m.range()).collect();
+    tag.replace_all(html, |tag: &Captures| {
+        let tag = tag.get(0).unwrap();
+        if comments
+            .iter()
+            .any(|comment| comment.contains(&tag.start()))
+        {
+            return tag.as_str().to_owned();
+        }
+        attr.replace_all(tag.as_str(), |captures: &Captures| {
+            let value = captures
+                .name("dq")
+                .or(captures.name("sq"))
+                .or(captures.name("uq"))
+                .unwrap()
+                .as_str();
+            let value = unescape(value);
+            let value = if captures["name"].eq_ignore_ascii_case("srcset") {
+                map_srcset(&value, &mut f)
+            } else {
+                f(value.trim())
+            };
+            format!(r#" {}="{}""#, &captures["name"], escape(&value))
+        })
+        .into_owned()
+    })
+    .into_owned()
+}
+
+/// The URLs in a `srcset` attribute. Candidates are separated by commas and
+/// consist of a URL and an optional descriptor, such as "img/a.png 2x".
+pub fn srcset_urls(srcset: &str) -> impl Iterator<Item = &str> {
+    srcset
+        .split(',')
+        .filter_map(|candidate| candidate.split_whitespace().next())
+}
+
+/// Replace the URLs in a `srcset` attribute, keeping their descriptors.
+pub fn map_srcset(srcset: &str, mut f: impl FnMut(&str) -> String) -> String {
+    srcset
+        .split(',')
+        .filter_map(|candidate| {
+            let mut parts = candidate.split_whitespace();
+            let url = f(parts.next()?);
+            Some(parts.fold(url, |candidate, descriptor| candidate + " " + descriptor))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A `srcset` with the variants of an image for high density displays by
+/// convention, such as `plot@2x.png` and `plot@3x.png` for `plot.png`. Return
+/// `None` if there are no such variants.
+pub fn density_variants(img: &str, resolver: &Resolver) -> Option<String> {
+    let path = Path::new(img);
+    let stem = path.file_stem()?.to_str()?;
+    let ext = path.extension()?.to_str()?;
+    let mut srcset = vec![format!("{} 1x", srcset_escape(img))];
+    for density in ["2x", "3x"] {
+        let variant = path.with_file_name(format!("{stem}@{density}.{ext}"));
+        let variant = variant.to_string_lossy();
+        if resolver.find(&variant).is_some() {
+            srcset.push(format!("{} {density}", srcset_escape(&variant)));
+        }
+    }
+    (srcset.len() > 1).then(|| srcset.join(", "))
+}
+
+/// Percent-encode the characters which separate the candidates of a `srcset`.
+fn srcset_escape(url: &str) -> String {
+    url.replace(' ', "%20").replace(',', "%2C")
+}
+
+/// Decode the character references which commonly appear in HTML attributes.
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// The dark theme variant of an image by convention, such as `plot.dark.png`
+/// for `plot.png`, if it exists.
+pub fn dark_variant(img: &str, resolver: &Resolver) -> Option<String> {
+    let path = Path::new(img);
+    let stem = path.file_stem()?.to_str()?;
+    let ext = path.extension()?.to_str()?;
+    if stem.ends_with(".dark") {
+        return None;
+    }
+    let variant = path.with_file_name(format!("{stem}.dark.{ext}"));
+    let variant = variant.to_string_lossy().into_owned();
+    resolver.find(&variant).map(|_| variant)
+}
+
+/// Get the MIME type of an image from its content, falling back to the
+/// extension if the content is not recognized. Error if the extension and the
+/// content disagree, since browsers might refuse to show the image.
+fn mime(path: &Path, data: &[u8]) -> &'static str {
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    let by_ext = ext.as_deref().and_then(extension_mime);
+    match (sniff(data), by_ext) {
+        // APNG files are PNG files with extra chunks.
+        (Some("image/png"), Some("image/apng")) => "image/apng",
+        (Some(sniffed), Some(by_ext)) if sniffed != by_ext => panic!(
+            r#"image content does not match its extension: "{}" contains {sniffed} data, but the extension suggests {by_ext}"#,
+            path.to_string_lossy()
+        ),
+        (Some(sniffed), _) => sniffed,
+        (None, Some(by_ext)) => by_ext,
+        (None, None) => match ext {
+            Some(ext) => panic!(r#"unsupported image format: "{ext}""#),
+            None => panic!(
+                r#"image path has no extension and its format is not recognized: "{}""#,
+                path.to_string_lossy()
+            ),
+        },
+    }
+}
+
+/// The element which shows the file with the given path, `video`, `audio` or
+/// `model` for 3D models. Return `None` for images.
+pub fn media_tag(path: &str) -> Option<&'static str> {
+    let ext = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+    match extension_mime(&ext)?.split_once('/')?.0 {
+        "video" => Some("video"),
+        "audio" => Some("audio"),
+        "model" => Some("model"),
+        _ => None,
+    }
+}
+
+/// Get the MIME type of an image, video, audio file or 3D model based on its
+/// extension.
+fn extension_mime(ext: &str) -> Option<&'static str> {
+    Some(match ext {
+        "apng" => "image/apng",
+        "avif" => "image/avif",
+        "gif" => "image/gif",
+        "jpg" | "jpeg" | "jfif" | "pjpeg" | "pjp" => "image/jpeg",
+        "png" => "image/png",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "ico" | "cur" => "image/x-icon",
+        "tif" | "tiff" => "image/tiff",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "ogv" => "video/ogg",
+        "ogg" | "oga" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp3" => "audio/mpeg",
+        "glb" => "model/gltf-binary",
+        "gltf" => "model/gltf+json",
+        "stl" => "model/stl",
+        "obj" => "model/obj",
+        _ => return None,
+    })
+}
+
+/// Look up a MIME type by name, such as one read from the cache. Return `None`
+/// if it is not supported.
+fn known_mime(name: &str) -> Option<&'static str> {
+    let ext = match name {
+        "image/svg+xml" => "svg",
+        "image/x-icon" => "ico",
+        "video/ogg" => "ogv",
+        "audio/mpeg" => "mp3",
+        "model/gltf-binary" => "glb",
+        "model/gltf+json" => "gltf",
+        _ => name.split_once('/')?.1,
+    };
+    extension_mime(ext).filter(|mime| *mime == name)
+}
+
+/// Detect the format of an image from its magic bytes. Return `None` if the
+/// content is not recognized.
+fn sniff(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some("image/png");
+    }
+    if data.starts_with(b"\xff\xd8\xff") {
+        return Some("image/jpeg");
+    }
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        return Some("image/gif");
+    }
+    if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        // The major brand is followed by a version and the compatible brands.
+        let len = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        let brands = data[8..len.clamp(12, data.len())]
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(i, _)| *i != 1)
+            .map(|(_, brand)| brand);
+        for brand in brands {
+            if brand == b"avif" || brand == b"avis" {
+                return Some("image/avif");
+            }
+        }
+        return None;
+    }
+    if data.starts_with(b"glTF") {
+        return Some("model/gltf-binary");
+    }
+    if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WAVE" {
+        return Some("audio/wav");
+    }
+    if data.starts_with(b"BM") && data.len() >= 14 {
+        return Some("image/bmp");
+    }
+    if data.starts_with(b"\0\0\x01\0") || data.starts_with(b"\0\0\x02\0") {
+        return Some("image/x-icon");
+    }
+    if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        return Some("image/tiff");
+    }
+    if is_svg(data) {
+        return Some("image/svg+xml");
+    }
+    None
+}
+
+/// Whether the data is an SVG document: XML whose root element is `<svg>`,
+/// possibly preceded by an XML declaration, comments and a doctype.
+fn is_svg(data: &[u8]) -> bool {
+    // Only the start of the document is inspected.
+    let head = &data[..data.len().min(4096)];
+    let Ok(mut text) = std::str::from_utf8(head).or_else(|err| {
+        // The head might cut a multi-byte character in half.
+        std::str::from_utf8(&head[..err.valid_up_to()])
+    }) else {
+        return false;
+    };
+    text = text.trim_start_matches('\u{feff}');
+    loop {
+        text = text.trim_start();
+        if let Some(rest) = text.strip_prefix("<?") {
+            let Some(end) = rest.find("?>") else {
+                return false;
+            };
+            text = &rest[end + 2..];
+        } else if let Some(rest) = text.strip_prefix("<!--") {
+            let Some(end) = rest.find("-->") else {
+                return false;
+            };
+            text = &rest[end + 3..];
+        } else if let Some(rest) = text.strip_prefix("<!") {
+            // A doctype, which can contain an internal subset in brackets.
+            let end = match (rest.find('['), rest.find('>')) {
+                (Some(open), Some(close)) if open < close => rest.find("]>").map(|end| end + 1),
+                (_, close) => close,
+            };
+            let Some(end) = end else {
+                return false;
+            };
+            text = &rest[end + 1..];
+        } else {
+            return text.starts_with("<svg")
+                && text[4..].starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/');
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Detects formats from their magic bytes.
+    #[test]
+    fn sniffing() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0"), Some("image/jpeg"));
+        assert_eq!(sniff(b"GIF89a"), Some("image/gif"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(
+            sniff(b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf"),
+            Some("image/avif")
+        );
+        assert_eq!(
+            sniff(b"\0\0\0\x18ftypmif1\0\0\0\0mif1avif"),
+            Some("image/avif")
+        );
+        assert_eq!(sniff(b"\0\0\0\x14ftypheic\0\0\0\0heic"), None);
+        assert_eq!(sniff(b"BM\0\0\0\0\0\0\0\0\0\0\0\0"), Some("image/bmp"));
+        assert_eq!(sniff(b"\0\0\x01\0\x01\0"), Some("image/x-icon"));
+        assert_eq!(sniff(b"II*\0"), Some("image/tiff"));
+        assert_eq!(sniff(b"MM\0*"), Some("image/tiff"));
+        assert_eq!(sniff(b"hello"), None);
+    }
+
+    /// Detects SVG documents after declarations, comments and doctypes.
+    #[test]
+    fn svg() {
+        assert!(is_svg(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"));
+        assert!(is_svg(
+            b"\xef\xbb\xbf<?xml version=\"1.0\"?>\n<!-- Drawn by hand -->\n<!DOCTYPE svg PUBLIC \
+              \"-//W3C//DTD SVG 1.1//EN\" \"x.dtd\" [<!ENTITY a \"b\">]>\n<svg>"
+        ));
+        assert!(!is_svg(b"<?xml version=\"1.0\"?><html>"));
+        assert!(!is_svg(b"<svgx>"));
+    }
+
+    /// Prefers the content over the extension, falling back to the extension.
+    #[test]
+    fn mime_types() {
+        assert_eq!(mime(Path::new("a"), b"GIF89a"), "image/gif");
+        assert_eq!(mime(Path::new("a.jpeg"), b"\xff\xd8\xff"), "image/jpeg");
+        assert_eq!(
+            mime(Path::new("a.apng"), b"\x89PNG\r\n\x1a\n"),
+            "image/apng"
+        );
+        assert_eq!(mime(Path::new("a.AVIF"), b"unknown"), "image/avif");
+        assert_eq!(known_mime("image/svg+xml"), Some("image/svg+xml"));
+        assert_eq!(known_mime("image/x-icon"), Some("image/x-icon"));
+        assert_eq!(known_mime("image/jpg"), None);
+        assert_eq!(
+            mime(Path::new("a.wav"), b"RIFF\0\0\0\0WAVEfmt "),
+            "audio/wav"
+        );
+        assert_eq!(mime(Path::new("a.webm"), b"\x1a\x45\xdf\xa3"), "video/webm");
+        assert_eq!(known_mime("video/ogg"), Some("video/ogg"));
+        assert_eq!(media_tag("media/run.MP4"), Some("video"));
+        assert_eq!(media_tag("media/beep.ogg"), Some("audio"));
+        assert_eq!(media_tag("img/a.png"), None);
+        assert_eq!(media_tag("models/bracket.glb"), Some("model"));
+        assert_eq!(
+            mime(Path::new("a.glb"), b"glTF\x02\0\0\0"),
+            "model/gltf-binary"
+        );
+        assert_eq!(known_mime("model/gltf+json"), Some("model/gltf+json"));
+    }
+
+    /// Finds images, videos and audio files in the attributes of HTML tags.
+    #[test]
+    fn html() {
+        assert_eq!(
+            html_sources(
+                r#"<picture>
+                  <source srcset="img/a.webp, img/a@2x.webp 2x" type="image/webp">
+                  <!-- <img src="img/old.png"> -->
+                  <IMG alt="a" SRC='img/a&amp;b.png'>
+                </picture>
+                <img src=img/c.png><video src="v.webm"></video>"#
+            ),
+            [
+                "img/a.webp",
+                "img/a@2x.webp",
+                "img/a&b.png",
+                "img/c.png",
+                "v.webm"
+            ]
+        );
+    }
+
+    /// Errors when the extension and the content disagree.
+    #[test]
+    #[should_panic(expected = "contains image/jpeg data, but the extension suggests image/png")]
+    fn mismatch() {
+        mime(Path::new("a.png"), b"\xff\xd8\xff\xe0");
+    }
+}
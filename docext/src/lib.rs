@@ -1,20 +1,31 @@
+#![cfg_attr(feature = "track-path", feature(proc_macro_tracked_path))]
+
 use {
+    crate::{
+        args::Args,
+        attrs::Attrs,
+        bib::{Bibliography, Citations},
+        config::Config,
+        identifier::Identifiers,
+        resolve::Resolver,
+    },
     base64::Engine,
     proc_macro::TokenStream,
     proc_macro2::{Ident, Span},
     quote::ToTokens,
     regex::Regex,
     std::{
-        collections::HashSet,
-        env,
-        fs,
-        path::{self, PathBuf},
+        collections::{BTreeSet, HashMap},
+        ops::Range,
+        path::PathBuf,
     },
     syn::{
+        parse_quote,
         punctuated::Punctuated,
         token::{Bracket, Eq, Pound},
         AttrStyle,
         Attribute,
+        Block,
         Expr,
         ExprLit,
         ImplItem,
@@ -26,12 +37,31 @@ use {
         Path,
         PathArguments,
         PathSegment,
+        Stmt,
         TraitItem,
     },
     url::Url,
 };
 
+mod admonition;
+mod args;
+mod asset;
+mod attachment;
+mod attrs;
+mod bib;
+mod budget;
+mod cache;
+mod config;
+mod figure;
+mod identifier;
+mod img;
 mod parser;
+#[cfg(feature = "processing")]
+mod process;
+mod resolve;
+mod svg;
+mod theorem;
+mod toc;
 
 // TODO:
 // - Remove the dependency on url and base64 and implement this manually instead
@@ -39,112 +69,126 @@ mod parser;
 //   (I.e. what is expected vs. what is not expected maybe.) Probably put it all
 //   in one comment for easier checking. Or maybe put them each on separate
 //   methods.
-// - Add a feature to skip the image size check.
 
 #[proc_macro_attribute]
 pub fn docext(attr: TokenStream, item: TokenStream) -> TokenStream {
-    if !attr.is_empty() {
-        panic!("#[docext] attribute does not take any arguments");
-    }
+    let args = Args::parse(attr);
 
-    // Try interpreting the input as a module item.
+    // Try interpreting the input as a module item. Associated functions and
+    // constants in impls are also parsed as module items, so the file
+    // dependencies can only be placed after the item if it can't appear inside
+    // of an impl. Otherwise, they are placed inside of the item body. Items
+    // without a body, such as type aliases, macro invocations and trait methods
+    // without a default body, can only be tracked with the "track-path" feature,
+    // see `track`.
     match syn::parse::<Item>(item).unwrap() {
         Item::Const(mut c) => {
-            update_doc(&mut c.attrs);
+            let files = update_doc(&mut c.attrs, &args);
+            track_in_expr(&mut c.expr, &files);
             c.to_token_stream().into()
         }
         Item::Enum(mut e) => {
-            update_doc(&mut e.attrs);
-            e.to_token_stream().into()
+            let files = update_doc(&mut e.attrs, &args);
+            with_dependencies(e, &files)
         }
         Item::ExternCrate(mut c) => {
-            update_doc(&mut c.attrs);
-            c.to_token_stream().into()
+            let files = update_doc(&mut c.attrs, &args);
+            with_dependencies(c, &files)
         }
         Item::Fn(mut f) => {
-            update_doc(&mut f.attrs);
+            let files = update_doc(&mut f.attrs, &args);
+            track_in_block(&mut f.block, &files);
             f.to_token_stream().into()
         }
         Item::ForeignMod(mut m) => {
-            update_doc(&mut m.attrs);
-            m.to_token_stream().into()
+            let files = update_doc(&mut m.attrs, &args);
+            with_dependencies(m, &files)
         }
         Item::Impl(mut i) => {
-            update_doc(&mut i.attrs);
-            i.to_token_stream().into()
+            let files = update_doc(&mut i.attrs, &args);
+            with_dependencies(i, &files)
         }
         Item::Macro(mut m) => {
-            update_doc(&mut m.attrs);
+            update_doc(&mut m.attrs, &args);
             m.to_token_stream().into()
         }
         Item::Mod(mut m) => {
-            update_doc(&mut m.attrs);
-            m.to_token_stream().into()
+            let files = update_doc(&mut m.attrs, &args);
+            with_dependencies(m, &files)
         }
         Item::Static(mut s) => {
-            update_doc(&mut s.attrs);
+            let files = update_doc(&mut s.attrs, &args);
+            track_in_expr(&mut s.expr, &files);
             s.to_token_stream().into()
         }
         Item::Struct(mut s) => {
-            update_doc(&mut s.attrs);
-            s.to_token_stream().into()
+            let files = update_doc(&mut s.attrs, &args);
+            with_dependencies(s, &files)
         }
         Item::Trait(mut t) => {
-            update_doc(&mut t.attrs);
-            t.to_token_stream().into()
+            let files = update_doc(&mut t.attrs, &args);
+            with_dependencies(t, &files)
         }
         Item::TraitAlias(mut t) => {
-            update_doc(&mut t.attrs);
-            t.to_token_stream().into()
+            let files = update_doc(&mut t.attrs, &args);
+            with_dependencies(t, &files)
         }
         Item::Type(mut t) => {
-            update_doc(&mut t.attrs);
+            update_doc(&mut t.attrs, &args);
             t.to_token_stream().into()
         }
         Item::Union(mut u) => {
-            update_doc(&mut u.attrs);
-            u.to_token_stream().into()
+            let files = update_doc(&mut u.attrs, &args);
+            with_dependencies(u, &files)
         }
         Item::Use(mut u) => {
-            update_doc(&mut u.attrs);
-            u.to_token_stream().into()
+            let files = update_doc(&mut u.attrs, &args);
+            with_dependencies(u, &files)
         }
         Item::Verbatim(v) => {
             // Try interpreting the input as a trait item.
             match syn::parse::<TraitItem>(v.into()).unwrap() {
                 TraitItem::Const(mut c) => {
-                    update_doc(&mut c.attrs);
+                    let files = update_doc(&mut c.attrs, &args);
+                    if let Some((_, expr)) = &mut c.default {
+                        track_in_expr(expr, &files);
+                    }
                     c.to_token_stream().into()
                 }
                 TraitItem::Fn(mut f) => {
-                    update_doc(&mut f.attrs);
+                    let files = update_doc(&mut f.attrs, &args);
+                    if let Some(block) = &mut f.default {
+                        track_in_block(block, &files);
+                    }
                     f.to_token_stream().into()
                 }
                 TraitItem::Type(mut t) => {
-                    update_doc(&mut t.attrs);
+                    update_doc(&mut t.attrs, &args);
                     t.to_token_stream().into()
                 }
                 TraitItem::Macro(mut m) => {
-                    update_doc(&mut m.attrs);
+                    update_doc(&mut m.attrs, &args);
                     m.to_token_stream().into()
                 }
                 TraitItem::Verbatim(v) => {
                     // Try interpreting the input as an impl item.
                     match syn::parse::<ImplItem>(v.into()).unwrap() {
                         ImplItem::Const(mut c) => {
-                            update_doc(&mut c.attrs);
+                            let files = update_doc(&mut c.attrs, &args);
+                            track_in_expr(&mut c.expr, &files);
                             c.to_token_stream().into()
                         }
                         ImplItem::Fn(mut f) => {
-                            update_doc(&mut f.attrs);
+                            let files = update_doc(&mut f.attrs, &args);
+                            track_in_block(&mut f.block, &files);
                             f.to_token_stream().into()
                         }
                         ImplItem::Type(mut t) => {
-                            update_doc(&mut t.attrs);
+                            update_doc(&mut t.attrs, &args);
                             t.to_token_stream().into()
                         }
                         ImplItem::Macro(mut m) => {
-                            update_doc(&mut m.attrs);
+                            update_doc(&mut m.attrs, &args);
                             m.to_token_stream().into()
                         }
                         other => panic!("unsupported impl item type {other:#?}"),
@@ -157,8 +201,10 @@ pub fn docext(attr: TokenStream, item: TokenStream) -> TokenStream {
     }
 }
 
-/// Update the doc comments with KaTeX syntax rendering and image support.
-fn update_doc(attrs: &mut Vec<Attribute>) {
+/// Update the doc comments with KaTeX syntax rendering, theorem environments,
+/// admonitions, citations, tables of contents and image support. Return the
+/// files embedded into the doc comment.
+fn update_doc(attrs: &mut Vec<Attribute>, args: &Args) -> Vec<PathBuf> {
     // Error if there is no doc comment, since #[docext] wouldn't do anything useful
     // in this case.
     if !attrs.iter().any(|attr| {
@@ -195,6 +241,11 @@ fn update_doc(attrs: &mut Vec<Attribute>) {
         })
         .collect();
 
+    let config = Config::load();
+    let resolver = Resolver::new(&config, base_dir(args));
+    // Files embedded into the doc comment, which the crate needs to depend on.
+    let mut files = Vec::new();
+
     // Markdown options used by rustdoc.
     let opts = pulldown_cmark::Options::ENABLE_TABLES
         | pulldown_cmark::Options::ENABLE_FOOTNOTES
@@ -202,15 +253,38 @@ fn update_doc(attrs: &mut Vec<Attribute>) {
         | pulldown_cmark::Options::ENABLE_TASKLISTS
         | pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION;
 
+    // Render theorem-like environments. This happens before the math is escaped,
+    // so that the environment headings are recognized as regular markdown.
+    let doc = theorem::render(&doc, opts);
+    // Render GitHub-style admonitions, which rustdoc would otherwise show as
+    // regular blockquotes.
+    let doc = admonition::render(&doc, opts);
+    // Generate the table of contents. The math in the entries is escaped along
+    // with the rest of the math below.
+    let doc = toc::render(&doc, opts, args.toc);
+    // Number the figures and link references to them.
+    let doc = figure::render(&doc, opts);
+
     // Spans of code blocks in the doc comment. These are needed to ensure that math
     // is not rendered inside of markdown code blocks.
     let mut code_sections = Vec::new();
+    // Spans of the doc comment which are not prose: code, HTML and links. Text
+    // replacements such as citations are not applied inside of these.
+    let mut verbatim_sections = Vec::new();
     // Collect code sections from doc comment.
     for (ev, range) in pulldown_cmark::Parser::new_ext(&doc, opts).into_offset_iter() {
-        if let pulldown_cmark::Event::Code(..)
-        | pulldown_cmark::Event::Start(pulldown_cmark::Tag::CodeBlock(..)) = ev
-        {
-            code_sections.push(range);
+        match ev {
+            pulldown_cmark::Event::Code(..)
+            | pulldown_cmark::Event::Start(pulldown_cmark::Tag::CodeBlock(..)) => {
+                code_sections.push(range.clone());
+                verbatim_sections.push(range);
+            }
+            pulldown_cmark::Event::Html(..)
+            | pulldown_cmark::Event::InlineHtml(..)
+            | pulldown_cmark::Event::Start(
+                pulldown_cmark::Tag::Link { .. } | pulldown_cmark::Tag::Image { .. },
+            ) => verbatim_sections.push(range),
+            _ => {}
         }
     }
 
@@ -220,16 +294,38 @@ fn update_doc(attrs: &mut Vec<Attribute>) {
     )
     .unwrap();
 
+    // Citations made in the doc comment. The bibliography is only loaded once the
+    // first citation is found.
+    let mut citations = None;
+    let identifiers = Identifiers::new();
+
     // Math sections in the doc comment, used to avoid encoding images as base64
     // data if they happen to be inside of a math block.
     let mut math_sections = Vec::new();
 
-    let mut doc: String = parser::parse_math(&doc)
-        .into_iter()
-        .map(|event| match event {
-            parser::Event::Text(text) => {
-                // Leave all regular markdown text unchanged.
-                text.to_owned()
+    let mut result = String::with_capacity(doc.len());
+    for event in parser::parse_math(&doc) {
+        match event {
+            parser::Event::Text(text, range) => {
+                // Replace citations and identifiers in prose, leave all other markdown text
+                // unchanged.
+                result.push_str(&map_outside(text, range, &verbatim_sections, |prose| {
+                    // Without a bibliography, text such as "[@octocat]" is left unchanged.
+                    let prose = match &config.bibliography {
+                        Some(path) if Citations::contains_citation(prose) => citations
+                            .get_or_insert_with(|| {
+                                files.push(path.clone());
+                                Citations::new(
+                                    Bibliography::load(path),
+                                    config.citation_style,
+                                    &asset::hash(doc.as_bytes())[..8],
+                                )
+                            })
+                            .replace(prose),
+                        _ => prose.to_owned(),
+                    };
+                    identifiers.autolink(&prose)
+                }));
             }
             parser::Event::Math(math, range)
                 if code_sections
@@ -238,57 +334,286 @@ fn update_doc(attrs: &mut Vec<Attribute>) {
             {
                 // Math sections inside code blocks are not rendered by KaTeX. Don't escape
                 // punctuation, leave them unchanged.
-                math.to_owned()
+                result.push_str(math);
             }
-            parser::Event::Math(math, range) => {
+            parser::Event::Math(math, _) => {
                 if math.lines().any(|line| line.trim().is_empty()) {
                     // The rustdoc markdown renderer interprets blank lines as starting a new
                     // paragraph, which would break the math.
                     panic!("blank lines in math blocks are not supported");
                 }
-                math_sections.push(range);
                 // Escape all punctuation characters. This is to ensure that the markdown
                 // renderer in rustdoc doesn't break the math. (Otherwise, for example starting
                 // a line with "-" (minus) in the math block would cause the
                 // markdown to render as a list and completely break the math,
                 // or for example writing $[a](b)$ would render as a link.)
-                punctuation.replace_all(math, r"\$punct").into_owned()
+                let math = punctuation.replace_all(math, r"\$punct");
+                math_sections.push(result.len()..result.len() + math.len());
+                result.push_str(&math);
             }
-        })
-        .collect();
+        }
+    }
+    let mut doc = result;
+
+    // Add the list of cited references to the end of the doc comment.
+    if let Some(citations) = citations {
+        doc.push_str(&citations.references());
+    }
 
-    // Paths to local images used in the doc comment.
-    let mut imgs = HashSet::new();
+    // Paths to local images used in the doc comment, ordered so that the output
+    // is reproducible.
+    let mut imgs = BTreeSet::new();
+    // Dark theme variants of the images, given as `dark=path` in the image title.
+    let mut dark = HashMap::new();
+    // Images followed by attributes such as `{width=400}`, which are replaced by
+    // HTML tags.
+    let mut replacements = Vec::new();
+    // The image currently being parsed, with its alt text.
+    let mut image = None;
+    // The link to a local file currently being parsed, with the range of its text.
+    let mut link: Option<FileLink> = None;
+    // Number of figures so far.
+    let mut figures = 0;
+    // Without JavaScript, markdown images link to the data of local images
+    // directly.
+    let data_urls = args.data_urls || config.images.data_urls;
+    // Whether there are 3D models, which require the viewer script.
+    let mut models = false;
+    let mut usage = budget::Usage::default();
     // Collect all images from the doc comment that are not URLs. These will be
     // encoded as base64 data and inserted into the doc comment as HTML tags, to
-    // be loaded and rendered by an image rendering script.
+    // be loaded and rendered by an image rendering script. Links to local files
+    // are checked, and embedded if they are attachments.
     for (ev, range) in pulldown_cmark::Parser::new_ext(&doc, opts).into_offset_iter() {
-        let pulldown_cmark::Event::Start(pulldown_cmark::Tag::Image {
-            dest_url: path_or_url,
-            ..
-        }) = ev
-        else {
-            // Not an image tag.
-            continue;
-        };
-        if Url::parse(&path_or_url).is_ok() {
-            // This is a URL, so it is not a local image.
-            continue;
+        if let Some(FileLink { text, .. }) = &mut link {
+            if ev != pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Link) {
+                *text = Some(match text.take() {
+                    Some(text) => text.start.min(range.start)..text.end.max(range.end),
+                    None => range.clone(),
+                });
+            }
         }
-        if math_sections
-            .iter()
-            .any(|section| section.start <= range.start && range.end <= section.end)
-        {
-            // The image is inside a math block, so it should not be encoded as base64.
-            // This avoids the following (extreme) edge case: if there is a math block such
-            // as $![img](path/does/not_exist.png)$, docext would panic because it couldn't
-            // find the image at that path. The root cause of the issue is that the markdown
-            // parser does not recognize math blocks, so it interprets the math
-            // as a regular image tag.
-            continue;
+        match ev {
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                ..
+            }) => {
+                if math_sections
+                    .iter()
+                    .any(|section| section.start <= range.start && range.end <= section.end)
+                    || matches!(
+                        link_type,
+                        pulldown_cmark::LinkType::Autolink | pulldown_cmark::LinkType::Email
+                    )
+                    || Url::parse(&dest_url).is_ok()
+                    || !attachment::extension(&dest_url).is_some_and(|ext| {
+                        config.is_attachment(&ext)
+                            || (config.check_links && attachment::is_checked(&ext))
+                    })
+                {
+                    continue;
+                }
+                link = Some(FileLink {
+                    range,
+                    dest: dest_url.into_string(),
+                    title: title.into_string(),
+                    text: None,
+                });
+            }
+            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Link) => {
+                let Some(FileLink {
+                    range,
+                    dest,
+                    title,
+                    text,
+                }) = link.take()
+                else {
+                    continue;
+                };
+                // Links to local files must not be broken.
+                let name = attachment::file_path(&dest);
+                let path = resolver.resolve(name);
+                let ext = path.extension().unwrap_or_default().to_string_lossy();
+                if !config.is_attachment(&ext) {
+                    continue;
+                }
+                // Files are embedded into the link, to be downloaded.
+                let (mime, data) = attachment::load(&path, name, &config.budgets);
+                usage.add(name, data.len() as u64);
+                let base64 = base64::engine::general_purpose::STANDARD.encode(&data);
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let html =
+                    attachment::html(&format!("data:{mime};base64,{base64}"), &file_name, &title);
+                match text {
+                    Some(text) => {
+                        replacements.push((range.start..text.start, html));
+                        replacements.push((text.end..range.end, "</a>".to_owned()));
+                    }
+                    None => replacements.push((range, html + "</a>")),
+                }
+                files.push(path);
+            }
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Image {
+                dest_url: path_or_url,
+                title,
+                ..
+            }) => {
+                if math_sections
+                    .iter()
+                    .any(|section| section.start <= range.start && range.end <= section.end)
+                {
+                    // The image is inside a math block, so it should not be encoded as base64.
+                    // This avoids the following (extreme) edge case: if there is a math block
+                    // such as $![img](path/does/not_exist.png)$, docext would panic because it
+                    // couldn't find the image at that path. The root cause of the issue is that
+                    // the markdown parser does not recognize math blocks, so it interprets the
+                    // math as a regular image tag.
+                    continue;
+                }
+                image = Some((
+                    range,
+                    path_or_url.to_string(),
+                    title.to_string(),
+                    String::new(),
+                ));
+                if Url::parse(&path_or_url).is_ok() || data_urls {
+                    // This is a URL, so it is not a local image, or it is linked directly.
+                    continue;
+                }
+                if let Some(variant) = title.trim().strip_prefix("dark=") {
+                    dark.entry(path_or_url.to_string())
+                        .or_insert_with(|| variant.trim().to_owned());
+                }
+                imgs.insert(path_or_url.into_string());
+            }
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::HtmlBlock)
+            | pulldown_cmark::Event::InlineHtml(_) => {
+                if math_sections
+                    .iter()
+                    .any(|section| section.start <= range.start && range.end <= section.end)
+                {
+                    continue;
+                }
+                // Images in raw HTML, such as <img> tags and the sources of <picture>
+                // elements. Without the script, they are linked to their data directly.
+                if data_urls {
+                    let html = img::map_html_sources(&doc[range.clone()], |src| {
+                        if src.is_empty() || Url::parse(src).is_ok() {
+                            return src.to_owned();
+                        }
+                        data_url(src, &config, &resolver, &mut usage, &mut files)
+                    });
+                    replacements.push((range, html));
+                    continue;
+                }
+                for src in img::html_sources(&doc[range]) {
+                    if Url::parse(&src).is_err() {
+                        imgs.insert(src);
+                    }
+                }
+            }
+            pulldown_cmark::Event::Text(text) | pulldown_cmark::Event::Code(text) => {
+                if let Some((.., alt)) = &mut image {
+                    alt.push_str(&text);
+                }
+            }
+            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Image) => {
+                let Some((range, src, title, alt)) = image.take() else {
+                    continue;
+                };
+                if config.images.require_alt && alt.trim().is_empty() {
+                    panic!(r#"image has no alt text: "{src}""#);
+                }
+                let local = Url::parse(&src).is_err();
+                let mut attrs = Attrs::parse(&doc[range.end..]);
+                // Local videos, audio files and 3D models are shown with their own elements.
+                let media = if local { img::media_tag(&src) } else { None };
+                if media.is_some() {
+                    attrs.get_or_insert_with(Default::default);
+                }
+                models |= media == Some("model");
+                // Local images with variants for high density displays get a srcset, which
+                // requires an HTML tag.
+                let srcset = attrs.as_ref().and_then(|(attrs, _)| attrs.srcset.clone());
+                let srcset = match srcset {
+                    Some(srcset) => Some(srcset),
+                    None if local && media.is_none() => img::density_variants(&src, &resolver),
+                    None => None,
+                };
+                if let Some(mut srcset) = srcset {
+                    if data_urls {
+                        srcset = img::map_srcset(&srcset, |url| match Url::parse(url) {
+                            Ok(_) => url.to_owned(),
+                            Err(_) => data_url(url, &config, &resolver, &mut usage, &mut files),
+                        });
+                    } else {
+                        for url in img::srcset_urls(&srcset) {
+                            if Url::parse(url).is_err() {
+                                imgs.insert(url.to_owned());
+                            }
+                        }
+                    }
+                    attrs.get_or_insert_with(Default::default).0.srcset = Some(srcset);
+                }
+                // The poster of a 3D model is embedded like any other image.
+                if let Some((attrs, _)) = &mut attrs {
+                    if let Some(poster) = attrs.poster.take() {
+                        attrs.poster = Some(match Url::parse(&poster) {
+                            Ok(_) => poster,
+                            Err(_) if data_urls => {
+                                data_url(&poster, &config, &resolver, &mut usage, &mut files)
+                            }
+                            Err(_) => {
+                                imgs.insert(poster.clone());
+                                poster
+                            }
+                        });
+                    }
+                }
+                let (src, title) = if data_urls && local {
+                    // Linked images can't change with the theme.
+                    let title = if title.trim().starts_with("dark=") {
+                        String::new()
+                    } else {
+                        title
+                    };
+                    let src = data_url(&src, &config, &resolver, &mut usage, &mut files);
+                    attrs.get_or_insert_with(Default::default);
+                    (src, title)
+                } else {
+                    (src, title)
+                };
+                let html = |attrs: &Attrs, alt: &str| match media {
+                    Some("model") => attrs.model(&src, alt, &title),
+                    Some(tag) => attrs.media(tag, &src, alt, &title),
+                    None => attrs.img(&src, alt, &title),
+                };
+                if let Some(caption) = alt.strip_prefix(figure::PREFIX) {
+                    // The image is a figure, the id belongs to the figure instead of the image.
+                    figures += 1;
+                    let (mut attrs, len) = attrs.unwrap_or_default();
+                    let id = attrs.id.take();
+                    replacements.push((
+                        range.start..range.end + len,
+                        figure::html(
+                            figures,
+                            id.as_deref(),
+                            &html(&attrs, caption.trim()),
+                            caption,
+                        ),
+                    ));
+                } else if let Some((attrs, len)) = attrs {
+                    replacements.push((range.start..range.end + len, html(&attrs, &alt)));
+                }
+            }
+            _ => {}
         }
-        imgs.insert(path_or_url.into_string());
     }
+    // Links are replaced after the images they contain, so the replacements have
+    // to be sorted.
+    let mut doc = splice(&doc, replacements);
 
     // Add the KaTeX CSS and JS to the doc comment, enabling TeX rending. Add a
     // rendering script which calls `renderMathInElement` on its parent, so
@@ -316,58 +641,232 @@ fn update_doc(attrs: &mut Vec<Attribute>) {
     // is done to facilitate high-quality IDE hovers, since putting the base64 data
     // directly in the middle of the hover could result in bad UX.
     for img in imgs.iter() {
-        // Load the image relative to the current crate.
-        let mut path = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
-        path.push(img);
-
-        // Ensure that the file is not too large, otherwise the compiler might crash.
-        let metadata = fs::metadata(&path).unwrap_or_else(|_| {
-            panic!(
-                r#"failed to stat image: "{}", is the file missing?"#,
-                path.to_string_lossy()
-            )
-        });
-        if metadata.len() > 1024 * 1024 {
-            panic!(
-                r#"image file too large: "{}", max size is 1MB"#,
-                path.to_string_lossy()
-            );
+        let mut data = embed(img, "data-", &config, &resolver, &mut usage, &mut files);
+        // Images can have a variant which is shown with dark themes.
+        let dark = dark
+            .get(img)
+            .cloned()
+            .or_else(|| img::dark_variant(img, &resolver));
+        if let Some(dark) = dark {
+            data.push_str(&embed(
+                &dark,
+                "data-dark-",
+                &config,
+                &resolver,
+                &mut usage,
+                &mut files,
+            ));
         }
 
-        // Encode the image as base64.
-        let data = fs::read(&path).unwrap_or_else(|_| {
-            panic!(
-                r#"failed to read image: "{}", is the file missing?"#,
-                path.to_string_lossy()
-            )
-        });
-        let base64 = base64::engine::general_purpose::STANDARD.encode(&data);
+        // Add a span containing the image data.
+        doc.push('\n');
+        doc.push_str(&format!(
+            r#"<span class="docext-img" data-src="{}"{data}></span>"#,
+            attrs::escape(img)
+        ));
+    }
 
-        // The data URL requires a MIME type.
-        let mime = mime(&path);
+    usage.finish(&config.budgets, location());
 
-        // Add a span containing the image data encoded as base64.
-        doc.push('\n');
+    if models && config.shared_assets && !imgs.is_empty() {
+        // The 3D model viewer is large, so it is shared like the images and run by
+        // the image script once it is loaded.
         doc.push_str(&format!(
-            r#"<span class="docext-img" data-src="{img}" data-img="data:{mime};base64,{base64}"></span>"#,
+            r#"
+<span class="docext-viewer" data-hash="{}"></span>"#,
+            asset::share("script", include_str!("viewer.js"))
         ));
+    } else if models {
+        // Add the 3D model viewer. Models are passed to it by the image script, or
+        // shown right away if they are linked to their data directly.
+        doc.push_str("\n<script>\n");
+        doc.push_str(include_str!("viewer.js"));
+        doc.push_str("</script>");
     }
 
     if !imgs.is_empty() {
         // Add the image rendering script.
-        doc.push_str(r#"
+        doc.push_str(&r#"
 <script>
 (function() {
     var elem = document.currentScript.parentElement;
-    document.addEventListener("DOMContentLoaded", function() {
+    // Shared assets are scripts in the documentation output, which are loaded
+    // before the images are rendered.
+    var store = "{store}";
+    function asset(hash, callback) {
+        var assets = window.docextAssets = window.docextAssets || {};
+        var loading = window.docextLoading = window.docextLoading || {};
+        if (assets.hasOwnProperty(hash)) {
+            callback(assets[hash]);
+            return;
+        }
+        if (!loading.hasOwnProperty(hash)) {
+            loading[hash] = [];
+            var root = document.querySelector("meta[name=rustdoc-vars]");
+            var script = document.createElement("script");
+            script.src = (root ? root.getAttribute("data-root-path") : "./") + store + hash + ".js";
+            script.onload = script.onerror = function(event) {
+                // Missing assets leave their images broken, instead of blocking the others.
+                if (event.type === "error") {
+                    console.error("docext: failed to load " + script.src + ", rebuild the " +
+                        "documentation with `cargo clean --doc && cargo doc`");
+                }
+                assets[hash] = assets[hash] || {};
+                loading[hash].forEach(function(callback) {
+                    callback(assets[hash]);
+                });
+            };
+            document.head.appendChild(script);
+        }
+        loading[hash].push(callback);
+    }
+    // Load the shared assets used in the doc comment into the image data spans.
+    function load(callback) {
+        var pending = 1;
+        function done() {
+            pending -= 1;
+            if (pending === 0) {
+                callback();
+            }
+        }
         elem.querySelectorAll(".docext-img").forEach(function(e) {
-            elem.querySelectorAll("img[src='" + e.getAttribute("data-src") + "']").forEach(function(i) {
-                i.src = e.getAttribute("data-img");
+            ["data-", "data-dark-"].forEach(function(prefix) {
+                var hash = e.getAttribute(prefix + "hash");
+                if (hash === null) {
+                    return;
+                }
+                pending += 1;
+                asset(hash, function(data) {
+                    for (var kind in data) {
+                        e.setAttribute(prefix + kind, data[kind]);
+                    }
+                    done();
+                });
             });
         });
+        // Run shared scripts, such as the 3D model viewer.
+        elem.querySelectorAll(".docext-viewer").forEach(function(e) {
+            pending += 1;
+            asset(e.getAttribute("data-hash"), function(data) {
+                if (data.script && !window.docextViewer) {
+                    var script = document.createElement("script");
+                    script.textContent = data.script;
+                    e.appendChild(script);
+                }
+                done();
+            });
+        });
+        done();
+    }
+    document.addEventListener("DOMContentLoaded", function() {
+        load(render);
     });
+    function render() {
+        // Rustdoc sets the current theme on the root element.
+        function dark() {
+            var theme = document.documentElement.getAttribute("data-theme");
+            return theme === "dark" || theme === "ayu";
+        }
+        var themed = [];
+        function observe(update) {
+            update();
+            themed.push(update);
+        }
+        // The image data for the current theme, as a data URL.
+        function url(e) {
+            var variants = e.hasAttribute("data-dark-img") || e.hasAttribute("data-dark-svg");
+            var prefix = variants && dark() ? "data-dark-" : "data-";
+            var markup = e.getAttribute(prefix + "svg");
+            return markup === null ? e.getAttribute(prefix + "img") : "data:image/svg+xml," + encodeURIComponent(markup);
+        }
+        // Paths are compared as resolved URLs, so that different spellings of the same
+        // path, such as "a b.png" and "a%20b.png", match.
+        function resolve(path) {
+            try {
+                return new URL(path, document.baseURI).href;
+            } catch (err) {
+                return path;
+            }
+        }
+        var images = {};
+        elem.querySelectorAll(".docext-img").forEach(function(e) {
+            images[resolve(e.getAttribute("data-src"))] = e;
+        });
+        elem.querySelectorAll("img[src], source[src], video[src], audio[src]").forEach(function(i) {
+            var src = resolve(i.getAttribute("src"));
+            if (!images.hasOwnProperty(src)) {
+                return;
+            }
+            var e = images[src];
+            var variants = e.hasAttribute("data-dark-img") || e.hasAttribute("data-dark-svg");
+            if (i.title.startsWith("dark=")) {
+                i.removeAttribute("title");
+            }
+            // SVG images can only be inlined outside of picture elements.
+            var inline = i.tagName === "IMG" && i.parentElement.tagName !== "PICTURE";
+            var current = i;
+            function update() {
+                var prefix = variants && dark() ? "data-dark-" : "data-";
+                var markup = e.getAttribute(prefix + "svg");
+                var next = i;
+                if (markup === null || !inline) {
+                    i.src = url(e);
+                    // Videos only pick up changed sources when they are reloaded.
+                    if (i.tagName === "SOURCE" && typeof i.parentElement.load === "function") {
+                        i.parentElement.load();
+                    }
+                } else {
+                    next = new DOMParser().parseFromString(markup, "image/svg+xml").documentElement;
+                    next.setAttribute("role", "img");
+                    next.setAttribute("aria-label", i.alt);
+                    next.classList.add("docext-svg");
+                }
+                if (next !== current) {
+                    current.replaceWith(next);
+                    current = next;
+                }
+            }
+            if (variants) {
+                observe(update);
+            } else {
+                update();
+            }
+        });
+        // 3D models are shown by the viewer script.
+        elem.querySelectorAll(".docext-model[data-model]").forEach(function(m) {
+            var src = resolve(m.getAttribute("data-model"));
+            if (images.hasOwnProperty(src) && window.docextViewer) {
+                window.docextViewer(m, url(images[src]));
+            }
+        });
+        // Images in srcset attributes, such as the sources of picture elements.
+        elem.querySelectorAll("[srcset]").forEach(function(i) {
+            var srcset = i.getAttribute("srcset");
+            observe(function() {
+                i.srcset = srcset.split(",").map(function(candidate) {
+                    var parts = candidate.trim().split(/\s+/);
+                    var src = resolve(parts[0]);
+                    if (images.hasOwnProperty(src)) {
+                        parts[0] = url(images[src]);
+                    }
+                    return parts.join(" ");
+                }).join(", ");
+            });
+        });
+        if (themed.length > 0) {
+            new MutationObserver(function() {
+                themed.forEach(function(update) {
+                    update();
+                });
+            }).observe(document.documentElement, {
+                attributes: true,
+                attributeFilter: ["data-theme"]
+            });
+        }
+    }
 })()
-</script>"#);
+</script>"#
+            .replace("{store}", &asset::store_path()));
     }
 
     // Create the modified doc attribute.
@@ -390,27 +889,343 @@ fn update_doc(attrs: &mut Vec<Attribute>) {
             }),
         }),
     });
+
+    track(&files);
+    files
+}
+
+/// A link to a local file, while it is being parsed.
+struct FileLink {
+    range: Range<usize>,
+    dest: String,
+    title: String,
+    /// The range of the link text, if it isn't empty.
+    text: Option<Range<usize>>,
+}
+
+/// Load a local image and return the attributes holding its data, with the
+/// given prefix. Inlined SVG images are stored as markup, to be inserted into
+/// the page, other images as base64 data URLs. With shared assets, only the
+/// hash of the data is stored, see [`asset::share`].
+fn embed(
+    img: &str,
+    prefix: &str,
+    config: &Config,
+    resolver: &Resolver,
+    usage: &mut budget::Usage,
+    files: &mut Vec<PathBuf>,
+) -> String {
+    let (mime, data) = load(img, config, resolver, usage, files, config.shared_assets);
+    let (kind, value) = if mime == "image/svg+xml" && config.images.inline_svg {
+        ("svg", String::from_utf8_lossy(&data).into_owned())
+    } else {
+        let base64 = base64::engine::general_purpose::STANDARD.encode(&data);
+        ("img", format!("data:{mime};base64,{base64}"))
+    };
+    if config.shared_assets {
+        format!(r#" {prefix}hash="{}""#, asset::share(kind, &value))
+    } else {
+        format!(r#" {prefix}{kind}="{}""#, attrs::escape(&value))
+    }
+}
+
+/// Load a local image as a base64 data URL.
+fn data_url(
+    img: &str,
+    config: &Config,
+    resolver: &Resolver,
+    usage: &mut budget::Usage,
+    files: &mut Vec<PathBuf>,
+) -> String {
+    let (mime, data) = load(img, config, resolver, usage, files, false);
+    let base64 = base64::engine::general_purpose::STANDARD.encode(&data);
+    format!("data:{mime};base64,{base64}")
+}
+
+/// Load a local image, recording its size and the dependency on it. Shared
+/// images are stored in the asset store rather than in the item.
+fn load(
+    img: &str,
+    config: &Config,
+    resolver: &Resolver,
+    usage: &mut budget::Usage,
+    files: &mut Vec<PathBuf>,
+    shared: bool,
+) -> (&'static str, Vec<u8>) {
+    let path = resolver.resolve(img);
+    let (mime, data) = img::load(&path, img, &config.images, &config.budgets);
+    if shared {
+        usage.add_shared(img, data.len() as u64);
+    } else {
+        usage.add(img, data.len() as u64);
+    }
+    files.push(path);
+    (mime, data)
+}
+
+/// The directory which relative paths in the doc comment are resolved against
+/// first: the base given as `#[docext(base = "...")]`, or the directory of the
+/// source file containing the item, if it is known.
+fn base_dir(args: &Args) -> Option<PathBuf> {
+    if let Some(base) = &args.base {
+        let dir = config::manifest_dir().join(base);
+        if !dir.is_dir() {
+            panic!(r#"base directory not found: "{base}""#);
+        }
+        return Some(dir);
+    }
+    if !proc_macro::is_available() {
+        return None;
+    }
+    // The path is relative to the working directory of the compiler.
+    let file = proc_macro::Span::call_site().local_file()?;
+    let file = std::env::current_dir().ok()?.join(file);
+    Some(file.parent()?.to_owned())
+}
+
+/// The location of the item being expanded, or `None` outside of a procedural
+/// macro, such as in unit tests, and outside of cargo builds. IDEs expand items
+/// again at new locations as they are edited, so their files would be counted
+/// towards the crate budget again and again.
+fn location() -> Option<String> {
+    if !proc_macro::is_available() || cache::target_dir().is_none() {
+        return None;
+    }
+    let span = proc_macro::Span::call_site();
+    Some(format!("{}:{}:{}", span.file(), span.line(), span.column()))
+}
+
+/// Make the crate depend on the given files with the unstable tracked path API,
+/// which works for all items, including those without a body. This requires a
+/// nightly compiler, so it is behind the "track-path" feature.
+#[cfg(feature = "track-path")]
+fn track(files: &[PathBuf]) {
+    if !proc_macro::is_available() {
+        return;
+    }
+    for file in files {
+        proc_macro::tracked::path(file);
+    }
+}
+
+#[cfg(not(feature = "track-path"))]
+fn track(_files: &[PathBuf]) {}
+
+/// Generate items which make the crate depend on the given files, so that it is
+/// rebuilt when any of them change. Cargo only knows about files read by the
+/// compiler, so each file is read with `include_bytes!` into a hidden constant.
+/// Only the length is kept, so that the file contents don't end up in the crate
+/// metadata.
+fn dependencies(files: &[PathBuf]) -> Vec<Item> {
+    files
+        .iter()
+        .map(|file| {
+            let file = file.to_string_lossy();
+            parse_quote!(
+                const _: usize = include_bytes!(#file).len();
+            )
+        })
+        .collect()
+}
+
+/// Emit the item followed by its file dependencies.
+fn with_dependencies(item: impl ToTokens, files: &[PathBuf]) -> TokenStream {
+    let mut tokens = item.to_token_stream();
+    for dependency in dependencies(files) {
+        dependency.to_tokens(&mut tokens);
+    }
+    tokens.into()
 }
 
-/// Get the MIME type of the given image path based on its extension.
-fn mime(path: &path::Path) -> &'static str {
-    let ext = path.extension().unwrap_or_else(|| {
-        panic!(
-            r#"image path has no extension: "{}""#,
-            path.to_string_lossy()
-        )
+/// Place the file dependencies at the start of a function body.
+fn track_in_block(block: &mut Block, files: &[PathBuf]) {
+    for dependency in dependencies(files).into_iter().rev() {
+        block.stmts.insert(0, Stmt::Item(dependency));
+    }
+}
+
+/// Wrap a constant expression in a block containing the file dependencies.
+fn track_in_expr(expr: &mut Expr, files: &[PathBuf]) {
+    if files.is_empty() {
+        return;
+    }
+    let dependencies = dependencies(files);
+    let inner = &*expr;
+    *expr = parse_quote!({
+        #(#dependencies)*
+        #inner
     });
-    match ext.to_string_lossy().as_ref() {
-        "apng" => "image/apng",
-        "avif" => "image/avif",
-        "gif" => "image/gif",
-        "jpg" | "jpeg" | "jfif" | "pjpeg" | "pjp" => "image/jpeg",
-        "png" => "image/png",
-        "svg" => "image/svg+xml",
-        "webp" => "image/webp",
-        "bmp" => "image/bmp",
-        "ico" | "cur" => "image/x-icon",
-        "tif" | "tiff" => "image/tiff",
-        _ => panic!(r#"unsupported image format: "{}""#, ext.to_string_lossy()),
+}
+
+/// Replace the given ranges of the doc comment, which must not overlap, and can
+/// be in any order.
+fn splice(doc: &str, mut replacements: Vec<(Range<usize>, String)>) -> String {
+    replacements.sort_by_key(|(range, _)| range.start);
+    let mut result = String::with_capacity(doc.len());
+    let mut last = 0;
+    for (range, text) in replacements {
+        result.push_str(&doc[last..range.start]);
+        result.push_str(&text);
+        last = range.end;
+    }
+    result.push_str(&doc[last..]);
+    result
+}
+
+/// Apply `f` to the parts of `text` which are outside of the given sections,
+/// leaving the rest unchanged. The `range` is the position of `text` in the
+/// doc comment, in the same coordinates as the sections.
+fn map_outside(
+    text: &str,
+    range: Range<usize>,
+    sections: &[Range<usize>],
+    mut f: impl FnMut(&str) -> String,
+) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut pos = range.start;
+    let mut sections: Vec<_> = sections
+        .iter()
+        .filter(|section| section.start < range.end && range.start < section.end)
+        .collect();
+    sections.sort_by_key(|section| section.start);
+    for section in sections {
+        let start = section.start.clamp(pos, range.end);
+        let end = section.end.clamp(pos, range.end);
+        result.push_str(&f(&text[pos - range.start..start - range.start]));
+        result.push_str(&text[start - range.start..end - range.start]);
+        pos = end;
+    }
+    result.push_str(&f(&text[pos - range.start..]));
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        syn::{ItemFn, ItemStruct},
+    };
+
+    /// Expanding the same item twice gives the same output, without absolute
+    /// paths.
+    #[test]
+    fn reproducible() {
+        let expand = || {
+            let mut item: ItemStruct = parse_quote! {
+                /// ![a](/example/img/pepega.png) ![b](/example/img/circle.svg)
+                /// ![c](/example/img/theme.svg) ![d](/example/img/density.png)
+                ///
+                /// <img src="/example/img/pepega2.png"> $x^2$
+                struct Reproducible;
+            };
+            update_doc(&mut item.attrs, &Args::default());
+            item.to_token_stream().to_string()
+        };
+        let output = expand();
+        assert_eq!(output, expand());
+        let workspace = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .unwrap();
+        assert!(!output.contains(&*workspace.to_string_lossy()));
+    }
+
+    /// Makes function bodies depend on the embedded files.
+    #[test]
+    fn dependencies() {
+        let mut item: ItemFn = parse_quote! {
+            /// ![a](/example/img/pepega.png) [b](/example/data/coeffs.csv)
+            fn f() {}
+        };
+        let files = update_doc(&mut item.attrs, &Args::default());
+        assert!(files[0].ends_with("example/data/coeffs.csv"));
+        assert!(files[1].ends_with("example/img/pepega.png"));
+        track_in_block(&mut item.block, &files);
+        let output = item.block.to_token_stream().to_string();
+        assert_eq!(output.matches("include_bytes !").count(), 2);
+        assert!(output.contains("example/img/pepega.png"));
+    }
+
+    /// Leaves bracketed text starting with "@" unchanged without a
+    /// bibliography.
+    #[test]
+    fn no_bibliography() {
+        let mut item: ItemStruct = parse_quote! {
+            /// Thanks to [@octocat].
+            struct NoBibliography;
+        };
+        update_doc(&mut item.attrs, &Args::default());
+        assert!(item
+            .to_token_stream()
+            .to_string()
+            .contains("Thanks to [@octocat]."));
+    }
+
+    /// Links images to their data directly, without a script.
+    #[test]
+    fn data_urls() {
+        let mut item: ItemStruct = parse_quote! {
+            /// ![a](/example/img/density.png "dark=/example/img/pepega.png")
+            ///
+            /// <picture><source srcset="/example/img/pepega.png 2x"><img src='/example/img/pepega.png'></picture>
+            struct DataUrls;
+        };
+        let args = Args {
+            data_urls: true,
+            ..Default::default()
+        };
+        update_doc(&mut item.attrs, &args);
+        let output = item.to_token_stream().to_string();
+        assert!(output.contains(r#"<img src=\"data:image/png;base64,"#));
+        assert!(output.contains(r#"srcset=\"data:image/png;base64,"#));
+        assert!(output.contains(r#"<source srcset=\"data:image/png;base64,"#));
+        assert!(!output.contains("src='/example/img/pepega.png'"));
+        assert!(!output.contains("dark="));
+        assert!(!output.contains("docext-img"));
+    }
+
+    /// Embeds linked files to be downloaded, leaving other links alone.
+    #[test]
+    fn attachments() {
+        let mut item: ItemStruct = parse_quote! {
+            /// [![data](/example/img/pepega.png)](/example/data/coeffs.csv), [b](Self),
+            /// [c](#c), [d](other/struct.D.html), [e](../guide/) and [f](example.com).
+            struct Attachments;
+        };
+        update_doc(&mut item.attrs, &Args::default());
+        let output = item.to_token_stream().to_string();
+        assert!(output.contains(
+            r#"<a href=\"data:text/csv;base64,eCx5CjAsMQoxLDAuNQoyLDAuMjUK\" download=\"coeffs.csv\">![data](/example/img/pepega.png)</a>, [b](Self)"#
+        ));
+        assert!(output
+            .contains("[c](#c), [d](other/struct.D.html), [e](../guide/) and [f](example.com)"));
+    }
+
+    /// Shows 3D models with the viewer script, along with their poster.
+    #[test]
+    fn models() {
+        let mut item: ItemStruct = parse_quote! {
+            /// ![cube](/example/models/cube.obj){poster=/example/img/bracket.png}
+            struct Models;
+        };
+        let args = Args {
+            data_urls: true,
+            ..Default::default()
+        };
+        update_doc(&mut item.attrs, &args);
+        let output = item.to_token_stream().to_string();
+        assert!(output.contains(r#"<span data-model=\"data:model/obj;base64,"#));
+        assert!(output.contains(r#"<img src=\"data:image/png;base64,"#));
+        assert!(output.contains("window.docextViewer"));
+    }
+
+    /// Errors on links to missing files.
+    #[test]
+    #[should_panic(expected = r#"file not found: "missing.toml""#)]
+    fn broken_links() {
+        let mut item: ItemStruct = parse_quote! {
+            /// [config](missing.toml#L2)
+            struct BrokenLinks;
+        };
+        update_doc(&mut item.attrs, &Args::default());
     }
 }
@@ -3,37 +3,40 @@ use std::ops::Range;
 /// Parse the given input into sequences of text and math blocks.
 ///
 /// This is implemented based on the [KaTeX auto-render script](https://github.com/KaTeX/KaTeX/blob/4f1d9166749ca4bd669381b84b45589f1500a476/contrib/auto-render/splitAtDelimiters.js).
-pub fn parse_math(mut text: &str) -> Vec<Event<'_>> {
+pub fn parse_math(text: &str) -> Vec<Event<'_>> {
     let mut events = Vec::new();
+    // Offset of the remaining input relative to the start of the text.
+    let mut offset = 0;
     loop {
-        if text.is_empty() {
+        let rest = &text[offset..];
+        if rest.is_empty() {
             return events;
         }
 
         // Find the start of the math block.
-        let Some(start) = text.find('$') else {
+        let Some(start) = rest.find('$') else {
             // There are no more math blocks.
-            events.push(Event::Text(text));
+            events.push(Event::Text(rest, offset..text.len()));
             return events;
         };
 
-        let delim = if text[start..].starts_with("$$") {
+        let delim = if rest[start..].starts_with("$$") {
             "$$"
         } else {
             "$"
         };
-        match find_math_end(text, delim, start) {
+        match find_math_end(rest, delim, start) {
             Some(end) => {
                 // Push the text before the math block, if there is any.
                 if start != 0 {
-                    events.push(Event::Text(&text[..start]));
+                    events.push(Event::Text(&rest[..start], offset..offset + start));
                 }
-                events.push(Event::Math(&text[start..end], start..end));
-                text = &text[end..];
+                events.push(Event::Math(&rest[start..end], offset + start..offset + end));
+                offset += end;
             }
             None => {
                 // There is no closing delimiter, so there is no math block.
-                events.push(Event::Text(text));
+                events.push(Event::Text(rest, offset..text.len()));
                 return events;
             }
         }
@@ -42,7 +45,7 @@ pub fn parse_math(mut text: &str) -> Vec<Event<'_>> {
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Event<'a> {
-    Text(&'a str),
+    Text(&'a str, Range<usize>),
     Math(&'a str, Range<usize>),
 }
 
@@ -86,11 +89,11 @@ mod test {
         Math(&'a str),
     }
 
-    fn parse_math(text: &str) -> Vec<Event> {
+    fn parse_math(text: &str) -> Vec<Event<'_>> {
         super::parse_math(text)
             .into_iter()
             .map(|event| match event {
-                super::Event::Text(text) => Event::Text(text),
+                super::Event::Text(text, _) => Event::Text(text),
                 super::Event::Math(math, _) => Event::Math(math),
             })
             .collect()
@@ -289,4 +292,18 @@ mod test {
             ]
         );
     }
+
+    /// Reports ranges relative to the start of the input.
+    #[test]
+    fn ranges() {
+        assert_eq!(
+            super::parse_math("a $b$ c $$d$$"),
+            vec![
+                super::Event::Text("a ", 0..2),
+                super::Event::Math("$b$", 2..5),
+                super::Event::Text(" c ", 5..8),
+                super::Event::Math("$$d$$", 8..13),
+            ]
+        );
+    }
 }
@@ -0,0 +1,253 @@
+use {
+    crate::config::{ConvertFormat, ImageConfig},
+    image::{
+        codecs::{
+            jpeg::JpegEncoder,
+            png::{CompressionType, FilterType, PngEncoder},
+            webp::WebPEncoder,
+        },
+        imageops,
+        DynamicImage,
+        ImageFormat,
+    },
+    std::path::Path,
+};
+
+/// Process an image before it is embedded: downscale it to the maximum width,
+/// convert formats that browsers can't show inline and recompress it. Formats
+/// which can't be decoded, or where processing would lose information such as
+/// animations, are returned unchanged.
+pub fn process(
+    path: &Path,
+    mime: &'static str,
+    data: Vec<u8>,
+    config: &ImageConfig,
+) -> (&'static str, Vec<u8>) {
+    let format = match mime {
+        "image/png" => ImageFormat::Png,
+        "image/jpeg" => ImageFormat::Jpeg,
+        "image/webp" => ImageFormat::WebP,
+        "image/bmp" => ImageFormat::Bmp,
+        "image/tiff" => ImageFormat::Tiff,
+        // SVG, GIF, APNG, AVIF and ICO images are embedded as-is.
+        _ => return (mime, data),
+    };
+    if is_animated(format, &data) {
+        return (mime, data);
+    }
+
+    let convert = match (format, config.convert) {
+        (ImageFormat::Bmp | ImageFormat::Tiff, Some(convert)) => Some(convert),
+        _ => None,
+    };
+    let img = image::load_from_memory_with_format(&data, format).unwrap_or_else(|err| {
+        panic!(
+            r#"failed to decode image: "{}": {err}"#,
+            path.to_string_lossy()
+        )
+    });
+    let resize = config
+        .max_width
+        .filter(|max_width| img.width() > *max_width);
+    if resize.is_none() && convert.is_none() && !config.recompress {
+        return (mime, data);
+    }
+
+    let img = match resize {
+        Some(width) => {
+            let height = ((img.height() as u64 * width as u64) / img.width() as u64).max(1) as u32;
+            img.resize_exact(width, height, imageops::FilterType::Lanczos3)
+        }
+        None => img,
+    };
+
+    let (mime, format) = match convert {
+        Some(ConvertFormat::Png) => ("image/png", ImageFormat::Png),
+        Some(ConvertFormat::WebP) => ("image/webp", ImageFormat::WebP),
+        None => (mime, format),
+    };
+    let processed = encode(&img, format, config).unwrap_or_else(|err| {
+        panic!(
+            r#"failed to encode image: "{}": {err}"#,
+            path.to_string_lossy()
+        )
+    });
+
+    // Recompressing an image that was not otherwise changed can make it larger, in
+    // which case the original is kept.
+    if resize.is_none() && convert.is_none() && processed.len() >= data.len() {
+        return (mime, data);
+    }
+    (mime, processed)
+}
+
+/// Whether the image is animated, which the decoder would reduce to its first
+/// frame. Animated PNG images have an `acTL` chunk before the image data, and
+/// animated WebP images an `ANIM` chunk.
+fn is_animated(format: ImageFormat, data: &[u8]) -> bool {
+    // The offset of the first chunk, whether the length comes before the type as
+    // in PNG, the type of the animation chunk and the types of the image data
+    // chunks, which come after it.
+    let (mut offset, png, animation, image_data): (_, _, &[u8], &[&[u8]]) = match format {
+        ImageFormat::Png => (8, true, b"acTL", &[b"IDAT"]),
+        ImageFormat::WebP => (12, false, b"ANIM", &[b"VP8 ", b"VP8L"]),
+        _ => return false,
+    };
+    while let Some(chunk) = data.get(offset..offset + 8) {
+        let (kind, length) = if png {
+            (
+                &chunk[4..],
+                u32::from_be_bytes(chunk[..4].try_into().unwrap()),
+            )
+        } else {
+            (
+                &chunk[..4],
+                u32::from_le_bytes(chunk[4..].try_into().unwrap()),
+            )
+        };
+        if kind == animation {
+            return true;
+        }
+        if image_data.contains(&kind) {
+            return false;
+        }
+        // PNG chunks end with a checksum, WebP chunks are padded to an even size.
+        let length = length as usize;
+        offset = offset.saturating_add(8).saturating_add(if png {
+            length + 4
+        } else {
+            length + length % 2
+        });
+    }
+    false
+}
+
+/// Encode the image in the given format, with the strongest compression
+/// available.
+fn encode(
+    img: &DynamicImage,
+    format: ImageFormat,
+    config: &ImageConfig,
+) -> image::ImageResult<Vec<u8>> {
+    let mut out = Vec::new();
+    match format {
+        ImageFormat::Png => img.write_with_encoder(PngEncoder::new_with_quality(
+            &mut out,
+            CompressionType::Best,
+            FilterType::Adaptive,
+        ))?,
+        // JPEG doesn't support transparency.
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut out, config.jpeg_quality))?,
+        ImageFormat::WebP => img.write_with_encoder(WebPEncoder::new_lossless(&mut out))?,
+        format => img.write_to(&mut std::io::Cursor::new(&mut out), format)?,
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, image::RgbaImage};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x % 256) as u8, (y % 256) as u8, 0, 255])
+        }));
+        let mut out = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut out), ImageFormat::Png)
+            .unwrap();
+        out
+    }
+
+    fn config() -> ImageConfig {
+        ImageConfig::default()
+    }
+
+    /// Downscales wide images, keeping the aspect ratio.
+    #[test]
+    fn downscale() {
+        let config = ImageConfig {
+            max_width: Some(100),
+            ..config()
+        };
+        let (mime, data) = process(Path::new("a.png"), "image/png", png(400, 200), &config);
+        assert_eq!(mime, "image/png");
+        let img = image::load_from_memory(&data).unwrap();
+        assert_eq!((img.width(), img.height()), (100, 50));
+    }
+
+    /// Leaves narrow images unchanged.
+    #[test]
+    fn narrow() {
+        let config = ImageConfig {
+            max_width: Some(100),
+            ..config()
+        };
+        let data = png(50, 50);
+        assert_eq!(
+            process(Path::new("a.png"), "image/png", data.clone(), &config),
+            ("image/png", data)
+        );
+    }
+
+    /// Leaves animated PNG images unchanged, which would otherwise lose all but
+    /// the first frame.
+    #[test]
+    fn animated() {
+        let config = ImageConfig {
+            max_width: Some(100),
+            recompress: true,
+            ..config()
+        };
+        // Insert an animation control chunk after the header chunk, which the
+        // animation detection looks for. The checksum isn't checked.
+        let mut data = png(400, 200);
+        let header_end = 8 + 8 + 13 + 4;
+        let mut actl = 8u32.to_be_bytes().to_vec();
+        actl.extend(b"acTL");
+        actl.extend(1u32.to_be_bytes());
+        actl.extend(0u32.to_be_bytes());
+        actl.extend([0; 4]);
+        data.splice(header_end..header_end, actl);
+        assert_eq!(
+            process(Path::new("a.png"), "image/png", data.clone(), &config),
+            ("image/png", data)
+        );
+        assert!(!is_animated(ImageFormat::Png, &png(10, 10)));
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X".to_vec();
+        webp.extend(10u32.to_le_bytes());
+        webp.extend([2, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        webp.extend(b"ANIM\x06\0\0\0");
+        assert!(is_animated(ImageFormat::WebP, &webp));
+    }
+
+    /// Converts BMP images to the configured format.
+    #[test]
+    fn convert() {
+        let mut bmp = Vec::new();
+        image::load_from_memory(&png(10, 10))
+            .unwrap()
+            .write_to(&mut std::io::Cursor::new(&mut bmp), ImageFormat::Bmp)
+            .unwrap();
+        let config = ImageConfig {
+            convert: Some(ConvertFormat::WebP),
+            ..config()
+        };
+        let (mime, data) = process(Path::new("a.bmp"), "image/bmp", bmp, &config);
+        assert_eq!(mime, "image/webp");
+        assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::WebP);
+    }
+
+    /// Never makes images larger by recompressing them.
+    #[test]
+    fn recompress() {
+        let config = ImageConfig {
+            recompress: true,
+            ..config()
+        };
+        let data = png(64, 64);
+        let (_, processed) = process(Path::new("a.png"), "image/png", data.clone(), &config);
+        assert!(processed.len() <= data.len());
+    }
+}
@@ -0,0 +1,206 @@
+use {
+    crate::config::{self, Config},
+    std::{
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Resolves the paths of files used in doc comments, such as images. Files
+/// must be inside of the crate, the workspace or one of the configured search
+/// paths, so that doc comments can't embed arbitrary files from the machine
+/// building the docs.
+#[derive(Debug)]
+pub struct Resolver {
+    /// Directories which relative paths are resolved against, in order.
+    bases: Vec<PathBuf>,
+    /// Directory which paths starting with `/` are resolved against.
+    workspace: PathBuf,
+    /// Directories which files must be inside of, canonicalized.
+    roots: Vec<PathBuf>,
+}
+
+impl Resolver {
+    /// Create a resolver which resolves relative paths against the given
+    /// directory first, then against the crate root and the search paths.
+    pub fn new(config: &Config, base: Option<PathBuf>) -> Self {
+        let krate = config::manifest_dir();
+        let workspace = workspace_root(&krate);
+        let mut roots = vec![krate.clone()];
+        roots.extend(config.search_paths.iter().cloned());
+        let bases = base.into_iter().chain(roots.iter().cloned()).collect();
+        let roots = roots
+            .iter()
+            .chain([&workspace])
+            .map(|root| {
+                root.canonicalize().unwrap_or_else(|_| {
+                    panic!(r#"search path not found: "{}""#, root.to_string_lossy())
+                })
+            })
+            .collect();
+        Self {
+            bases,
+            workspace,
+            roots,
+        }
+    }
+
+    /// Resolve a path used in the doc comment to a file. Paths starting with
+    /// `/` are relative to the workspace root, other paths are relative to the
+    /// base directory, the crate root or one of the search paths. Return `None`
+    /// if the file doesn't exist.
+    pub fn find(&self, path: &str) -> Option<PathBuf> {
+        // Paths in markdown and HTML can be percent-encoded, such as "my%20plot.png".
+        // Files whose names contain a literal percent sign are found as well.
+        let mut spellings = vec![path.to_owned()];
+        spellings.extend(decode(path).filter(|decoded| decoded != path));
+        let mut candidates = Vec::new();
+        for path in &spellings {
+            match path.strip_prefix('/') {
+                Some(path) => candidates.push(self.workspace.join(path)),
+                None => candidates.extend(self.bases.iter().map(|base| base.join(path))),
+            }
+        }
+        let file = candidates.into_iter().find(|file| file.is_file())?;
+        let file = file.canonicalize().ok()?;
+        if !self.roots.iter().any(|root| file.starts_with(root)) {
+            panic!(
+                r#"file is outside of the crate, the workspace and the search paths: "{path}" resolves to "{}""#,
+                file.to_string_lossy()
+            );
+        }
+        Some(file)
+    }
+
+    /// Resolve a path used in the doc comment to a file, or error if it doesn't
+    /// exist.
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        self.find(path).unwrap_or_else(|| {
+            let searched: Vec<_> = if path.starts_with('/') {
+                vec![self.workspace.to_string_lossy()]
+            } else {
+                self.bases
+                    .iter()
+                    .map(|base| base.to_string_lossy())
+                    .collect()
+            };
+            panic!(
+                r#"file not found: "{path}", searched in "{}""#,
+                searched.join(r#"", ""#)
+            )
+        })
+    }
+}
+
+/// Decode percent-encoded characters in a path. Return `None` if the path is
+/// not validly encoded.
+fn decode(path: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Find the root of the workspace containing the crate, which is the closest
+/// ancestor with a `Cargo.toml` containing a `[workspace]` table. A crate
+/// outside of a workspace is its own workspace.
+fn workspace_root(krate: &Path) -> PathBuf {
+    for dir in krate.ancestors() {
+        let Ok(manifest) = fs::read_to_string(dir.join("Cargo.toml")) else {
+            continue;
+        };
+        let is_workspace = manifest
+            .parse::<toml::Table>()
+            .is_ok_and(|manifest| manifest.contains_key("workspace"));
+        if is_workspace {
+            return dir.to_owned();
+        }
+    }
+    krate.to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn resolver() -> Resolver {
+        let krate = Path::new(env!("CARGO_MANIFEST_DIR"));
+        Resolver {
+            bases: vec![krate.to_owned()],
+            workspace: krate.parent().unwrap().to_owned(),
+            roots: vec![
+                krate.canonicalize().unwrap(),
+                krate.parent().unwrap().canonicalize().unwrap(),
+            ],
+        }
+    }
+
+    /// Resolves paths relative to the crate and the workspace.
+    #[test]
+    fn resolve() {
+        let resolver = resolver();
+        assert!(resolver
+            .resolve("src/lib.rs")
+            .ends_with("docext/src/lib.rs"));
+        assert!(resolver
+            .resolve("/example/Cargo.toml")
+            .ends_with("example/Cargo.toml"));
+        assert_eq!(resolver.find("missing.png"), None);
+    }
+
+    /// Finds the workspace root.
+    #[test]
+    fn workspace() {
+        let krate = Path::new(env!("CARGO_MANIFEST_DIR"));
+        assert_eq!(workspace_root(krate), krate.parent().unwrap());
+    }
+
+    /// Errors on files outside of the allowed directories.
+    #[test]
+    #[should_panic(expected = "file is outside of the crate")]
+    fn outside() {
+        resolver().find("../../../../../../../../../../etc/passwd");
+    }
+
+    /// Decodes percent-encoded paths.
+    #[test]
+    fn percent_encoding() {
+        assert_eq!(decode("my%20plot.png").unwrap(), "my plot.png");
+        assert_eq!(decode("%E6%97%A5%E6%9C%AC.png").unwrap(), "日本.png");
+        assert_eq!(decode("it's%2Fa.png").unwrap(), "it's/a.png");
+        assert_eq!(decode("100%.png"), None);
+        assert!(resolver()
+            .resolve("src/%6Cib.rs")
+            .ends_with("docext/src/lib.rs"));
+    }
+
+    /// Prefers files in the base directory.
+    #[test]
+    fn base() {
+        let krate = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let resolver = Resolver {
+            bases: vec![krate.join("src"), krate.to_owned()],
+            ..resolver()
+        };
+        assert!(resolver.resolve("lib.rs").ends_with("docext/src/lib.rs"));
+        assert!(resolver
+            .resolve("Cargo.toml")
+            .ends_with("docext/Cargo.toml"));
+    }
+
+    /// Errors on missing files.
+    #[test]
+    #[should_panic(expected = r#"file not found: "missing.png""#)]
+    fn missing() {
+        resolver().resolve("missing.png");
+    }
+}
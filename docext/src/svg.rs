@@ -0,0 +1,419 @@
+use {
+    quick_xml::{
+        events::{BytesCData, BytesStart, BytesText, Event},
+        name::ResolveResult,
+        NsReader,
+        Writer,
+    },
+    regex::{Captures, Regex},
+    std::{path::Path, sync::LazyLock},
+};
+
+const SVG_NAMESPACE: &[u8] = b"http://www.w3.org/2000/svg";
+const XLINK_NAMESPACE: &[u8] = b"http://www.w3.org/1999/xlink";
+const XML_NAMESPACE: &[u8] = b"http://www.w3.org/XML/1998/namespace";
+
+/// SVG elements which are kept. Other elements are removed along with their
+/// content: scripts can run when the SVG is inlined into the page, foreign
+/// objects and elements from other namespaces can contain arbitrary HTML, and
+/// metadata is only useful to editors.
+const ELEMENTS: &[&str] = &[
+    "a",
+    "animate",
+    "animateMotion",
+    "animateTransform",
+    "circle",
+    "clipPath",
+    "defs",
+    "desc",
+    "ellipse",
+    "feBlend",
+    "feColorMatrix",
+    "feComponentTransfer",
+    "feComposite",
+    "feConvolveMatrix",
+    "feDiffuseLighting",
+    "feDisplacementMap",
+    "feDistantLight",
+    "feDropShadow",
+    "feFlood",
+    "feFuncA",
+    "feFuncB",
+    "feFuncG",
+    "feFuncR",
+    "feGaussianBlur",
+    "feImage",
+    "feMerge",
+    "feMergeNode",
+    "feMorphology",
+    "feOffset",
+    "fePointLight",
+    "feSpecularLighting",
+    "feSpotLight",
+    "feTile",
+    "feTurbulence",
+    "filter",
+    "g",
+    "image",
+    "line",
+    "linearGradient",
+    "marker",
+    "mask",
+    "mpath",
+    "path",
+    "pattern",
+    "polygon",
+    "polyline",
+    "radialGradient",
+    "rect",
+    "set",
+    "stop",
+    "style",
+    "svg",
+    "switch",
+    "symbol",
+    "text",
+    "textPath",
+    "title",
+    "tspan",
+    "use",
+    "view",
+];
+
+/// Elements whose whitespace is significant.
+const TEXT: &[&str] = &["text", "tspan", "textPath", "title", "desc", "style"];
+
+/// Sanitize and minify an SVG image. Elements other than known SVG elements,
+/// event handlers and references to external resources are removed, as are
+/// comments, editor metadata and whitespace between elements.
+///
+/// Elements and attributes are matched by their namespace and local name, so
+/// that prefixes can't be used to get past the sanitizer. Elements are written
+/// without their prefix, and `xlink:href` as `href`, so that the result works
+/// the same when it is inlined into an HTML page, which ignores namespaces.
+pub fn sanitize(path: &Path, data: &[u8]) -> Vec<u8> {
+    let error = |err: &dyn std::fmt::Display| -> ! {
+        panic!(
+            r#"failed to parse SVG image: "{}": {err}"#,
+            path.to_string_lossy()
+        )
+    };
+
+    let mut reader = NsReader::from_reader(data);
+    let mut writer = Writer::new(Vec::new());
+    // Local names of the open elements.
+    let mut stack: Vec<String> = Vec::new();
+    // Depth inside a removed element.
+    let mut removed = 0;
+    loop {
+        let (namespace, event) = reader
+            .read_resolved_event()
+            .unwrap_or_else(|err| error(&err));
+        let event = match event {
+            Event::Eof => break,
+            Event::Start(start) => {
+                if removed > 0 || is_removed(&namespace, &start) {
+                    if stack.is_empty() {
+                        error(&"the root element is not an SVG element");
+                    }
+                    removed += 1;
+                    continue;
+                }
+                let root = stack.is_empty();
+                let start = attributes(&reader, &start, root).unwrap_or_else(|err| error(&err));
+                stack.push(String::from_utf8_lossy(start.name().as_ref()).into_owned());
+                Event::Start(start)
+            }
+            Event::Empty(start) => {
+                if removed > 0 || is_removed(&namespace, &start) {
+                    if stack.is_empty() {
+                        error(&"the root element is not an SVG element");
+                    }
+                    continue;
+                }
+                let start =
+                    attributes(&reader, &start, stack.is_empty()).unwrap_or_else(|err| error(&err));
+                Event::Empty(start)
+            }
+            Event::End(_) => {
+                if removed > 0 {
+                    removed -= 1;
+                    continue;
+                }
+                let name = stack.pop().unwrap_or_default();
+                Event::End(BytesStart::new(name).to_end().into_owned())
+            }
+            _ if removed > 0 => continue,
+            Event::Text(text) => {
+                let parent = stack.last().map(String::as_str);
+                if parent == Some("style") {
+                    let css = text.unescape().unwrap_or_else(|err| error(&err));
+                    Event::Text(BytesText::new(&sanitize_css(&css)).into_owned())
+                } else if text.iter().all(u8::is_ascii_whitespace)
+                    && !parent.is_some_and(|parent| TEXT.contains(&parent))
+                {
+                    continue;
+                } else {
+                    Event::Text(text)
+                }
+            }
+            Event::CData(cdata) => {
+                if stack.last().map(String::as_str) == Some("style") {
+                    let css = String::from_utf8_lossy(&cdata);
+                    Event::CData(BytesCData::new(sanitize_css(&css)).into_owned())
+                } else {
+                    Event::CData(cdata)
+                }
+            }
+            // Comments, processing instructions, the XML declaration and the doctype,
+            // which could declare external entities.
+            Event::Comment(_) | Event::PI(_) | Event::Decl(_) | Event::DocType(_) => continue,
+        };
+        writer.write_event(event).unwrap();
+    }
+    if !stack.is_empty() {
+        error(&"unclosed elements");
+    }
+    writer.into_inner()
+}
+
+/// Whether the element is removed along with its content. Elements without a
+/// namespace are taken to be SVG elements, as the root element gets the SVG
+/// namespace if it is missing.
+fn is_removed(namespace: &ResolveResult, start: &BytesStart) -> bool {
+    let svg = match namespace {
+        ResolveResult::Unbound => true,
+        ResolveResult::Bound(namespace) => namespace.as_ref() == SVG_NAMESPACE,
+        ResolveResult::Unknown(_) => false,
+    };
+    let name = start.local_name();
+    let name = String::from_utf8_lossy(name.as_ref());
+    if !svg || !ELEMENTS.contains(&name.as_ref()) {
+        return true;
+    }
+    // Animations can set attributes to arbitrary values, including links to
+    // scripts.
+    matches!(name.as_ref(), "set" | "animate")
+        && start.attributes().flatten().any(|attr| {
+            attr.key.as_ref() == b"attributeName"
+                && attr.unescape_value().is_ok_and(|value| {
+                    let value = value.trim().to_ascii_lowercase();
+                    value.starts_with("on") || value == "href" || value.ends_with(":href")
+                })
+        })
+}
+
+/// Copy the element without its prefix, leaving out event handlers,
+/// references to external resources, namespace declarations and attributes
+/// from other namespaces, such as editor metadata. The root element gets the
+/// SVG namespace, which is required for the image to render.
+fn attributes<R>(
+    reader: &NsReader<R>,
+    start: &BytesStart,
+    root: bool,
+) -> quick_xml::Result<BytesStart<'static>> {
+    let name = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+    let mut result = BytesStart::new(name.clone());
+    let mut keys = Vec::new();
+    for attr in start.attributes() {
+        let attr = attr?;
+        if attr.key.as_namespace_binding().is_some() {
+            continue;
+        }
+        let (namespace, local) = reader.resolve_attribute(attr.key);
+        let local = String::from_utf8_lossy(local.as_ref()).into_owned();
+        let key = match namespace {
+            ResolveResult::Unbound => local,
+            ResolveResult::Bound(namespace) if namespace.as_ref() == XLINK_NAMESPACE => local,
+            ResolveResult::Bound(namespace) if namespace.as_ref() == XML_NAMESPACE => {
+                format!("xml:{local}")
+            }
+            _ => continue,
+        };
+        let value = attr.unescape_value()?;
+        let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+        if key.to_ascii_lowercase().starts_with("on") || keys.contains(&key) {
+            continue;
+        }
+        if matches!(key.as_str(), "href" | "src") && !allowed_link(&name, &value) {
+            continue;
+        }
+        // Presentation attributes, such as `fill`, take CSS values.
+        let value = if key == "style" {
+            sanitize_css(&value)
+        } else if is_external(&value) {
+            continue;
+        } else {
+            value
+        };
+        result.push_attribute((key.as_str(), value.as_str()));
+        keys.push(key);
+    }
+    if root {
+        result.push_attribute(("xmlns", "http://www.w3.org/2000/svg"));
+    }
+    Ok(result)
+}
+
+/// Whether a link is allowed. Links to fragments inside the document and to
+/// embedded images are allowed everywhere, hyperlinks to web pages are allowed
+/// on `<a>` elements.
+fn allowed_link(element: &str, link: &str) -> bool {
+    let link = link.trim().to_ascii_lowercase();
+    if link.starts_with('#') {
+        return true;
+    }
+    if element == "a" {
+        return link.starts_with("https:") || link.starts_with("http:");
+    }
+    link.starts_with("data:image/") && !link.starts_with("data:image/svg")
+}
+
+/// Remove references to external resources from CSS. Declarations and rules
+/// which could load anything other than a fragment of the document, such as
+/// `@import` rules and `url()` values, are removed as a whole.
+fn sanitize_css(css: &str) -> String {
+    static COMMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)/\*.*?\*/").unwrap());
+    let css = COMMENT.replace_all(css, "");
+    let mut result = String::new();
+    let mut start = 0;
+    for (i, c) in css.match_indices([';', '{', '}']) {
+        let declaration = &css[start..i];
+        if !is_external(declaration) {
+            result.push_str(declaration);
+            result.push_str(c);
+        } else if c != ";" {
+            // Keep the braces, so that the following rules are not affected.
+            result.push_str(c);
+        }
+        start = i + 1;
+    }
+    if !is_external(&css[start..]) {
+        result.push_str(&css[start..]);
+    }
+    result
+}
+
+/// Whether a piece of CSS could load an external resource. CSS escapes are
+/// resolved and case is ignored, since both are ignored by browsers. Only
+/// `url()` and `src()` values pointing to a fragment of the document are
+/// allowed.
+fn is_external(css: &str) -> bool {
+    static ESCAPE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"\\(?:(?<hex>[0-9a-fA-F]{1,6})[ \t\r\n\f]?|(?<char>[\s\S])|$)").unwrap()
+    });
+    static FUNCTION: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"(?<name>url|src|image|image-set|cross-fade)\(\s*['"]?(?<start>.?)"#).unwrap()
+    });
+    let css = ESCAPE.replace_all(css, |captures: &Captures| {
+        if let Some(hex) = captures.name("hex") {
+            u32::from_str_radix(hex.as_str(), 16)
+                .ok()
+                .and_then(char::from_u32)
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                .to_string()
+        } else {
+            captures
+                .name("char")
+                .map_or("", |c| c.as_str())
+                .trim_start_matches(['\n', '\r', '\x0c'])
+                .to_owned()
+        }
+    });
+    let css = css.to_ascii_lowercase();
+    css.contains("@import")
+        || FUNCTION.captures_iter(&css).any(|captures| {
+            !matches!(&captures["name"], "url" | "src") || &captures["start"] != "#"
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sanitize(svg: &str) -> String {
+        String::from_utf8(super::sanitize(Path::new("a.svg"), svg.as_bytes())).unwrap()
+    }
+
+    /// Removes scripts, event handlers and external references.
+    #[test]
+    fn scripts() {
+        assert_eq!(
+            sanitize(
+                r##"<svg xmlns="http://www.w3.org/2000/svg" onload="alert(1)">
+                    <script>alert(2)</script>
+                    <a href="javascript:alert(3)"><rect onclick="alert(4)" fill="url(#g)"/></a>
+                    <image href="https://example.com/tracker.png"/>
+                    <use xlink:href="other.svg#shape"/>
+                    <set attributeName="href" to="javascript:alert(5)"/>
+                    <foreignObject><div>html</div></foreignObject>
+                    <h:iframe xmlns:h="http://www.w3.org/1999/xhtml" srcdoc="&lt;script&gt;alert(6)&lt;/script&gt;"/>
+                    <svg:script xmlns:svg="http://www.w3.org/2000/svg">alert(7)</svg:script>
+                    <use xmlns:x="http://www.w3.org/1999/xlink" x:href="https://evil.example/a.svg#x"/>
+                    <use xmlns:x="http://www.w3.org/1999/xlink" x:href="#shape"/>
+                    <div><img src="x" onerror="alert(8)"/></div>
+                </svg>"##
+            ),
+            r##"<svg xmlns="http://www.w3.org/2000/svg"><a><rect fill="url(#g)"/></a><image/><use/><use/><use href="#shape"/></svg>"##
+        );
+    }
+
+    /// Removes comments, metadata and whitespace between elements, but keeps
+    /// whitespace in text.
+    #[test]
+    fn minify() {
+        assert_eq!(
+            sanitize(
+                "<?xml version=\"1.0\"?>\n<!-- Created with Inkscape -->\n<svg \
+                 xmlns:inkscape=\"x\" inkscape:version=\"1.0\" viewBox=\"0  0\n10 10\">\n  \
+                 <metadata><rdf/></metadata>\n  <sodipodi:namedview/>\n  <text>a \
+                 <tspan>b</tspan></text>\n</svg>\n"
+            ),
+            "<svg viewBox=\"0 0 10 10\" xmlns=\"http://www.w3.org/2000/svg\"><text>a \
+             <tspan>b</tspan></text></svg>"
+        );
+    }
+
+    /// Removes imports and declarations with external URLs from stylesheets.
+    #[test]
+    fn css() {
+        assert_eq!(
+            sanitize(
+                "<svg><style>@import url(https://x.com/a.css); rect { fill: \
+                 url('https://x.com/a.png'); stroke: url(#g) }</style></svg>"
+            ),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\"><style> rect { stroke: url(#g) \
+             }</style></svg>"
+        );
+    }
+
+    /// Ignores case, comments and escapes when looking for external URLs.
+    #[test]
+    fn css_bypasses() {
+        assert_eq!(
+            sanitize(
+                r##"<svg><style>@IMPORT "https://x.com/a.css"; @im\70 ort "b.css"; a { fill: URL(https://x.com/a.png); stroke: u\72l(https://x.com/b.png); color: red; fill: u/**/rl(https://x.com/c.png); background: image-set("https://x.com/d.png" 1x); mask: -webkit-image-set(url(#m) 1x, "e.png" 2x); filter: Url( "#f" ) }</style></svg>"##
+            ),
+            r##"<svg xmlns="http://www.w3.org/2000/svg"><style> a { color: red; filter: Url( &quot;#f&quot; ) }</style></svg>"##
+        );
+    }
+
+    /// Removes presentation attributes and style declarations with external
+    /// URLs.
+    #[test]
+    fn css_attributes() {
+        assert_eq!(
+            sanitize(
+                r##"<svg><rect fill="URL(https://x.com/a.png)" stroke="url(#g)" style="fill: \75 rl(https://x.com/b.png); color: red"/></svg>"##
+            ),
+            r##"<svg xmlns="http://www.w3.org/2000/svg"><rect stroke="url(#g)" style=" color: red"/></svg>"##
+        );
+    }
+
+    /// Errors on malformed SVG images.
+    #[test]
+    #[should_panic(expected = "failed to parse SVG image")]
+    fn malformed() {
+        sanitize("<svg><g></svg>");
+    }
+}
@@ -0,0 +1,273 @@
+use {
+    crate::attrs::escape,
+    pulldown_cmark::{Event, Options, Parser, Tag},
+    regex::Regex,
+    std::ops::Range,
+};
+
+/// Render theorem, lemma, definition and proof environments.
+///
+/// Environments are written either as a blockquote starting with a bold
+/// heading, such as `> **Theorem (Name).** ...`, or as a fenced block opened
+/// by `:::theorem Name` and closed by `:::`. Theorem-like environments are
+/// numbered in order of appearance, separately for each kind. Proofs are
+/// rendered as collapsible `<details>` elements ending with a QED mark.
+pub fn render(doc: &str, opts: Options) -> String {
+    // Environments must not be recognized inside of code blocks.
+    let code_blocks: Vec<Range<usize>> = Parser::new_ext(doc, opts)
+        .into_offset_iter()
+        .filter_map(|(ev, range)| matches!(ev, Event::Start(Tag::CodeBlock(..))).then_some(range))
+        .collect();
+
+    let quote_line = Regex::new(r"^\s{0,4}>\s?(?<rest>.*)$").unwrap();
+    let quote_head = Regex::new(
+        r"^\*\*(?i:(?<kind>theorem|lemma|corollary|proposition|definition|proof))(?:\s*\((?<name>[^)]*)\))?\.?\*\*\.?\s*(?<rest>.*)$",
+    )
+    .unwrap();
+    let fence_open = Regex::new(
+        r"^\s*:::\s*(?i:(?<kind>theorem|lemma|corollary|proposition|definition|proof))\b\s*(?<name>.*)$",
+    )
+    .unwrap();
+    let fence_close = Regex::new(r"^\s*:::\s*$").unwrap();
+
+    let mut counters = [0; Kind::ALL.len()];
+    let mut result = String::with_capacity(doc.len());
+    // Kinds of the currently open fenced environments.
+    let mut fences = Vec::new();
+    // Kind of the currently open blockquote environment.
+    let mut quote = None;
+    // Whether any environment was rendered, so the styling needs to be added.
+    let mut used = false;
+
+    let mut offset = 0;
+    for line in doc.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let content = line.trim_end_matches(['\n', '\r']);
+
+        if code_blocks.iter().any(|block| block.contains(&start)) {
+            if let Some(kind) = quote.take() {
+                result.push_str(&close(kind));
+            }
+            result.push_str(line);
+            continue;
+        }
+
+        // Continue or close the current blockquote environment.
+        if let Some(kind) = quote {
+            if let Some(captures) = quote_line.captures(content) {
+                result.push_str(&captures["rest"]);
+                result.push('\n');
+                continue;
+            }
+            result.push_str(&close(kind));
+            quote = None;
+        }
+
+        if let Some(head) = quote_line
+            .captures(content)
+            .and_then(|captures| quote_head.captures(captures.name("rest").unwrap().as_str()))
+        {
+            let kind = Kind::parse(&head["kind"]);
+            let name = head.name("name").map(|name| name.as_str());
+            result.push_str(&open(kind, name, &mut counters));
+            result.push_str(&head["rest"]);
+            result.push('\n');
+            quote = Some(kind);
+            used = true;
+        } else if let Some(captures) = fence_open.captures(content) {
+            let kind = Kind::parse(&captures["kind"]);
+            let name = captures["name"]
+                .trim()
+                .trim_start_matches('(')
+                .trim_end_matches(')');
+            let name = (!name.is_empty()).then_some(name);
+            result.push_str(&open(kind, name, &mut counters));
+            fences.push(kind);
+            used = true;
+        } else if fence_close.is_match(content) && !fences.is_empty() {
+            result.push_str(&close(fences.pop().unwrap()));
+        } else {
+            result.push_str(line);
+        }
+    }
+
+    if let Some(kind) = quote {
+        result.push_str(&close(kind));
+    }
+    if !fences.is_empty() {
+        panic!(
+            r#"unclosed "{}" environment, expected ":::""#,
+            fences[0].label()
+        );
+    }
+
+    if used {
+        result.push_str(STYLE);
+    }
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Theorem,
+    Lemma,
+    Corollary,
+    Proposition,
+    Definition,
+    Proof,
+}
+
+impl Kind {
+    const ALL: [Kind; 6] = [
+        Kind::Theorem,
+        Kind::Lemma,
+        Kind::Corollary,
+        Kind::Proposition,
+        Kind::Definition,
+        Kind::Proof,
+    ];
+
+    fn parse(kind: &str) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.label().eq_ignore_ascii_case(kind))
+            .unwrap()
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Theorem => "Theorem",
+            Kind::Lemma => "Lemma",
+            Kind::Corollary => "Corollary",
+            Kind::Proposition => "Proposition",
+            Kind::Definition => "Definition",
+            Kind::Proof => "Proof",
+        }
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            Kind::Definition => "docext-definition",
+            _ => "docext-theorem",
+        }
+    }
+}
+
+/// The HTML and markdown opening an environment, including its heading.
+fn open(kind: Kind, name: Option<&str>, counters: &mut [usize]) -> String {
+    let name = name
+        .map(|name| format!(" ({})", escape(name)))
+        .unwrap_or_default();
+    if kind == Kind::Proof {
+        // Blank lines are needed for the markdown inside of the HTML to be rendered,
+        // here and in the other elements wrapping markdown, such as admonitions.
+        return format!("<details class=\"docext-proof\">\n<summary>Proof{name}.</summary>\n\n");
+    }
+    let count = &mut counters[Kind::ALL.iter().position(|k| *k == kind).unwrap()];
+    *count += 1;
+    format!(
+        "<div class=\"docext-env {}\">\n\n**{} {count}{name}.** ",
+        kind.class(),
+        kind.label()
+    )
+}
+
+/// The HTML closing an environment.
+fn close(kind: Kind) -> String {
+    if kind == Kind::Proof {
+        "\n<div class=\"docext-qed\">∎</div>\n</details>\n\n".to_owned()
+    } else {
+        "\n</div>\n\n".to_owned()
+    }
+}
+
+/// Styling shared by all environments.
+const STYLE: &str = r#"
+<style>
+.docext-env, .docext-proof {
+    margin: 0.75em 0;
+    padding: 0.25em 1em;
+    border-left: 3px solid var(--border-color, #ccc);
+}
+.docext-theorem {
+    font-style: italic;
+}
+.docext-theorem strong {
+    font-style: normal;
+}
+.docext-proof > summary {
+    cursor: pointer;
+    font-style: italic;
+}
+.docext-qed {
+    text-align: right;
+}
+</style>"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn render(doc: &str) -> String {
+        super::render(doc, Options::empty())
+    }
+
+    /// Renders a blockquote environment with a name.
+    #[test]
+    fn blockquote() {
+        let doc = render(" > **Theorem (Pythagoras).** Some text\n > more text\n\n After\n");
+        assert!(doc.starts_with(
+            "<div class=\"docext-env docext-theorem\">\n\n**Theorem 1 (Pythagoras).** Some \
+             text\nmore text\n\n</div>\n\n\n After\n"
+        ));
+    }
+
+    /// Escapes names, which are inserted into HTML.
+    #[test]
+    fn escaped_name() {
+        assert!(render(" :::theorem a < b\n x\n :::\n").contains("**Theorem 1 (a &lt; b).**"));
+        assert!(
+            render(" :::proof <i>\n x\n :::\n").contains("<summary>Proof (&lt;i&gt;).</summary>")
+        );
+    }
+
+    /// Renders a fenced environment without a name.
+    #[test]
+    fn fence() {
+        let doc = render(" :::lemma\n Some text\n :::\n");
+        assert!(doc.starts_with(
+            "<div class=\"docext-env docext-theorem\">\n\n**Lemma 1.**  Some text\n\n</div>\n\n"
+        ));
+    }
+
+    /// Numbers each kind of environment separately.
+    #[test]
+    fn numbering() {
+        let doc = render(
+            " :::theorem\n a\n :::\n\n :::lemma\n b\n :::\n\n > **Theorem.** c\n\n > \
+             **Definition.** d\n",
+        );
+        assert!(doc.contains("**Theorem 1.**"));
+        assert!(doc.contains("**Lemma 1.**"));
+        assert!(doc.contains("**Theorem 2.**"));
+        assert!(doc.contains("**Definition 1.**"));
+    }
+
+    /// Renders proofs as collapsible elements ending with a QED mark.
+    #[test]
+    fn proof() {
+        let doc = render(" > **Proof.** Trivial.\n");
+        assert!(doc.starts_with(
+            "<details class=\"docext-proof\">\n<summary>Proof.</summary>\n\nTrivial.\n\n<div \
+             class=\"docext-qed\">∎</div>\n</details>\n\n"
+        ));
+    }
+
+    /// Leaves regular blockquotes and code blocks unchanged.
+    #[test]
+    fn unchanged() {
+        let doc = " > Just a quote\n\n ```\n :::theorem\n ```\n";
+        assert_eq!(super::render(doc, Options::empty()), doc);
+    }
+}
@@ -0,0 +1,300 @@
+use {
+    crate::splice,
+    pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd},
+    regex::Regex,
+    std::{ops::Range, sync::LazyLock},
+};
+
+/// Replace the `[[toc]]` marker with a table of contents linking to all
+/// headings in the doc comment.
+///
+/// If `always` is set and there is no marker, the table of contents is
+/// inserted after the first paragraph, so that it doesn't become the item
+/// summary. Each heading gets an anchor with a slug derived from its text,
+/// where math is replaced by its TeX source. The math in the table of contents
+/// entries is kept, so that it is rendered like the math in the headings.
+pub fn render(doc: &str, opts: Options, always: bool) -> String {
+    static MARKER: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?m)^[ \t]*\[\[toc\]\][ \t]*\r?\n?").unwrap());
+
+    let mut code_blocks = Vec::new();
+    let mut headings = Vec::new();
+    // End of the first top-level block, used as the position of the table of
+    // contents if there is no marker.
+    let mut summary_end = None;
+    // Depth of nested blocks.
+    let mut depth = 0;
+    // Links in the headings, with the range of their text. Links can't be nested,
+    // so they are replaced by their text in the entries, which are links
+    // themselves.
+    let mut links: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+    // The link currently being parsed, with the range of its text so far.
+    let mut link: Option<(Range<usize>, Option<Range<usize>>)> = None;
+    for (ev, range) in Parser::new_ext(doc, opts).into_offset_iter() {
+        if let Some((_, text)) = &mut link {
+            if ev != Event::End(TagEnd::Link) {
+                *text = Some(match text.take() {
+                    Some(text) => text.start.min(range.start)..text.end.max(range.end),
+                    None => range.clone(),
+                });
+            }
+        }
+        match ev {
+            Event::Start(Tag::Link { link_type, .. })
+                if headings
+                    .last()
+                    .is_some_and(|(_, heading): &(_, Range<usize>)| {
+                        heading.contains(&range.start)
+                    }) =>
+            {
+                // The range of collapsed reference links leaves out the "[]".
+                let mut range = range;
+                if link_type == LinkType::Collapsed && doc[range.end..].starts_with("[]") {
+                    range.end += 2;
+                }
+                link = Some((range, None));
+                depth += 1;
+            }
+            Event::End(TagEnd::Link) if link.is_some() => {
+                let (range, text) = link.take().unwrap();
+                links.push((range.clone(), text.unwrap_or(range.start..range.start)));
+                depth -= 1;
+            }
+            Event::Start(Tag::CodeBlock(..)) => {
+                code_blocks.push(range);
+                depth += 1;
+            }
+            Event::Start(Tag::Heading { level, .. }) if depth == 0 => {
+                headings.push((level, range));
+                depth += 1;
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(end) => {
+                depth -= 1;
+                if depth == 0 && summary_end.is_none() && !matches!(end, TagEnd::Heading(..)) {
+                    summary_end = Some(range.end);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let markers: Vec<Range<usize>> = MARKER
+        .find_iter(doc)
+        .map(|m| m.range())
+        .filter(|m| !code_blocks.iter().any(|block| block.contains(&m.start)))
+        .collect();
+    if markers.is_empty() && !always {
+        return doc.to_owned();
+    }
+
+    // Insertions into the doc comment, as positions and inserted text.
+    let mut insertions: Vec<(Range<usize>, String)> = Vec::new();
+    let mut slugs: Vec<String> = Vec::new();
+    let mut toc = String::from("<nav class=\"docext-toc\">\n\n");
+    let min_level = headings
+        .iter()
+        .map(|(level, _)| *level as usize)
+        .min()
+        .unwrap_or(1);
+    let mut prev_indent = 0;
+    for (level, range) in headings {
+        let replacements = links
+            .iter()
+            .filter(|(link, _)| range.contains(&link.start))
+            .map(|(link, text)| {
+                (
+                    link.start - range.start..link.end - range.start,
+                    doc[text.clone()].to_owned(),
+                )
+            })
+            .collect();
+        let text = heading_text(&splice(&doc[range.clone()], replacements));
+        let mut slug = slug(&text);
+        // Make the slug unique within the doc comment.
+        let base = slug.clone();
+        let mut i = 1;
+        while slugs.contains(&slug) {
+            slug = format!("{base}-{i}");
+            i += 1;
+        }
+        slugs.push(slug.clone());
+
+        // Headings always start a new line, the anchor goes right before it.
+        let line_start = doc[..range.start].rfind('\n').map_or(0, |i| i + 1);
+        insertions.push((
+            line_start..line_start,
+            format!("<div id=\"toc-{slug}\"></div>\n\n"),
+        ));
+
+        // Nest the entries by heading level, but never by more than one level at a
+        // time, since that would be parsed as a code block.
+        let indent = (level as usize - min_level).min(prev_indent + 1);
+        prev_indent = indent;
+        toc.push_str(&"  ".repeat(indent));
+        toc.push_str(&format!("- [{text}](#toc-{slug})\n"));
+    }
+    toc.push_str("\n</nav>\n\n");
+    toc.push_str(STYLE);
+    if slugs.is_empty() {
+        // Without headings, only the markers are removed.
+        let markers = markers.into_iter().map(|m| (m, String::new())).collect();
+        return splice(doc, markers);
+    }
+
+    match markers.first() {
+        Some(_) => {
+            // Only the first marker is replaced with the table of contents.
+            for (i, m) in markers.into_iter().enumerate() {
+                let text = if i == 0 { toc.clone() } else { String::new() };
+                insertions.push((m, text));
+            }
+        }
+        None => {
+            let at = summary_end.unwrap_or(0);
+            let newlines = if doc[..at].ends_with('\n') {
+                "\n"
+            } else {
+                "\n\n"
+            };
+            insertions.push((at..at, format!("{newlines}{toc}")));
+        }
+    }
+
+    splice(doc, insertions)
+}
+
+/// The markdown source of a heading, without the heading markers.
+fn heading_text(heading: &str) -> String {
+    let heading = heading.trim();
+    if heading.starts_with('#') {
+        // ATX heading, such as "## Title ##".
+        static CLOSING: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+#+$").unwrap());
+        let heading = heading.trim_start_matches('#');
+        CLOSING.replace(heading, "").trim().to_owned()
+    } else {
+        // Setext heading, underlined by "===" or "---".
+        let lines: Vec<_> = heading.lines().collect();
+        lines[..lines.len().saturating_sub(1)]
+            .iter()
+            .map(|line| line.trim())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Create a slug for a heading. Math is replaced with its TeX source without
+/// backslashes, so that for example "The $\pi$ constant" becomes
+/// "the-pi-constant".
+fn slug(text: &str) -> String {
+    static MATH: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\$\$?(?<tex>[^$]*)\$\$?").unwrap());
+    let text = MATH.replace_all(text, |captures: &regex::Captures| {
+        format!(" {} ", captures["tex"].replace('\\', " "))
+    });
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "section".to_owned()
+    } else {
+        slug.to_owned()
+    }
+}
+
+/// Styling of the table of contents.
+const STYLE: &str = r#"<style>
+.docext-toc ul ul {
+    margin-top: 0;
+}
+</style>
+"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Replaces the marker with a nested list of links.
+    #[test]
+    fn marker() {
+        let doc = render(
+            " Summary.\n\n [[toc]]\n\n # One\n\n ## Two\n\n #### Four\n\n # One\n",
+            Options::empty(),
+            false,
+        );
+        assert!(doc.contains(
+            "<nav class=\"docext-toc\">\n\n- [One](#toc-one)\n  - [Two](#toc-two)\n    - \
+             [Four](#toc-four)\n- [One](#toc-one-1)\n\n</nav>"
+        ));
+        assert!(!doc.contains("[[toc]]"));
+        assert!(doc.contains("<div id=\"toc-one\"></div>\n\n # One"));
+        assert!(doc.contains("<div id=\"toc-one-1\"></div>\n\n # One"));
+    }
+
+    /// Inserts the table of contents after the summary if requested.
+    #[test]
+    fn always() {
+        let doc = render(" Summary.\n\n # One\n", Options::empty(), true);
+        assert!(doc.starts_with(" Summary.\n\n<nav class=\"docext-toc\">"));
+    }
+
+    /// Inserts nothing if there are no headings, but still removes the marker.
+    #[test]
+    fn no_headings() {
+        let doc = " Summary.\n\n More text.\n";
+        assert_eq!(render(doc, Options::empty(), true), doc);
+        assert_eq!(
+            render(" Summary.\n\n [[toc]]\n\n More.\n", Options::empty(), false),
+            " Summary.\n\n\n More.\n"
+        );
+    }
+
+    /// Doesn't change anything without a marker.
+    #[test]
+    fn no_marker() {
+        let doc = " Summary.\n\n # One\n\n ```\n [[toc]]\n ```\n";
+        assert_eq!(render(doc, Options::empty(), false), doc);
+    }
+
+    /// Creates sensible slugs for headings containing math and markup.
+    #[test]
+    fn slugs() {
+        assert_eq!(slug("The $\\pi$ constant"), "the-pi-constant");
+        assert_eq!(slug("Using `Vec<T>` *well*"), "using-vec-t-well");
+        assert_eq!(slug("$$O(n^2)$$"), "o-n-2");
+        assert_eq!(slug("$$"), "section");
+    }
+
+    /// Replaces links in the entries with their text, since links can't be
+    /// nested.
+    #[test]
+    fn links() {
+        let doc = render(
+            " [[toc]]\n\n # See [*Foo*](Foo), [Bar][], [Baz][bar] and <https://x.org>\n\n [Bar]: \
+             crate::Bar\n",
+            Options::empty(),
+            false,
+        );
+        assert!(doc.contains(
+            "- [See *Foo*, Bar, Baz and https://x.org](#toc-see-foo-bar-baz-and-https-x-org)"
+        ));
+        assert!(doc.contains(" # See [*Foo*](Foo), [Bar][], [Baz][bar] and <https://x.org>\n"));
+    }
+
+    /// Keeps math in the entries, so that it is rendered.
+    #[test]
+    fn math() {
+        let doc = render(
+            " [[toc]]\n\n # The $\\pi$ constant\n",
+            Options::empty(),
+            false,
+        );
+        assert!(doc.contains("- [The $\\pi$ constant](#toc-the-pi-constant)"));
+    }
+}
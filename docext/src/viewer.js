@@ -0,0 +1,343 @@
+// Minimal WebGL viewer for 3D models embedded by docext. Supports binary and
+// ASCII STL, OBJ, and glTF 2.0, either binary or with embedded buffers. Models
+// are drawn in the text color of the theme, shaded by a fixed light.
+(function() {
+    var elem = document.currentScript.parentElement;
+    if (window.docextViewer) {
+        start();
+        return;
+    }
+    // Decode a data URL.
+    function bytes(url) {
+        var comma = url.indexOf(",");
+        var data = url.slice(comma + 1);
+        if (!/;base64$/.test(url.slice(0, comma))) {
+            return new TextEncoder().encode(decodeURIComponent(data));
+        }
+        var binary = atob(data);
+        var result = new Uint8Array(binary.length);
+        for (var i = 0; i < binary.length; i++) {
+            result[i] = binary.charCodeAt(i);
+        }
+        return result;
+    }
+    function text(data) {
+        return new TextDecoder().decode(data);
+    }
+    // The parsers return the vertex positions of the triangles of the model.
+    function stl(data) {
+        var view = new DataView(data.buffer, data.byteOffset, data.byteLength);
+        var positions = [];
+        var count = data.byteLength >= 84 ? view.getUint32(80, true) : -1;
+        if (84 + count * 50 === data.byteLength) {
+            for (var i = 0; i < count; i++) {
+                for (var j = 0; j < 9; j++) {
+                    positions.push(view.getFloat32(84 + i * 50 + 12 + j * 4, true));
+                }
+            }
+            return positions;
+        }
+        var vertex = /vertex\s+(\S+)\s+(\S+)\s+(\S+)/g;
+        var source = text(data);
+        var match;
+        while ((match = vertex.exec(source)) !== null) {
+            positions.push(+match[1], +match[2], +match[3]);
+        }
+        return positions;
+    }
+    function obj(data) {
+        var vertices = [];
+        var positions = [];
+        text(data).split("\n").forEach(function(line) {
+            var parts = line.trim().split(/\s+/);
+            if (parts[0] === "v") {
+                vertices.push([+parts[1], +parts[2], +parts[3]]);
+            } else if (parts[0] === "f") {
+                var face = parts.slice(1).map(function(part) {
+                    var index = parseInt(part.split("/")[0], 10);
+                    return vertices[index < 0 ? vertices.length + index : index - 1];
+                });
+                // Faces are convex polygons, which are split into triangles.
+                for (var i = 1; i + 1 < face.length; i++) {
+                    positions.push.apply(positions, face[0].concat(face[i], face[i + 1]));
+                }
+            }
+        });
+        return positions;
+    }
+    // Multiply two 4x4 matrices in column-major order.
+    function multiply(a, b) {
+        var result = [];
+        for (var column = 0; column < 4; column++) {
+            for (var row = 0; row < 4; row++) {
+                var sum = 0;
+                for (var k = 0; k < 4; k++) {
+                    sum += a[k * 4 + row] * b[column * 4 + k];
+                }
+                result.push(sum);
+            }
+        }
+        return result;
+    }
+    function gltf(data) {
+        var json;
+        var binary;
+        var view = new DataView(data.buffer, data.byteOffset, data.byteLength);
+        // Binary glTF files start with "glTF" and consist of a JSON and a binary chunk.
+        if (data.byteLength >= 12 && view.getUint32(0, true) === 0x46546c67) {
+            for (var offset = 12; offset + 8 <= data.byteLength; offset += 8 + length) {
+                var length = view.getUint32(offset, true);
+                var type = view.getUint32(offset + 4, true);
+                var chunk = data.subarray(offset + 8, offset + 8 + length);
+                if (type === 0x4e4f534a) {
+                    json = JSON.parse(text(chunk));
+                } else if (type === 0x004e4942) {
+                    binary = chunk;
+                }
+            }
+        } else {
+            json = JSON.parse(text(data));
+        }
+        var buffers = (json.buffers || []).map(function(buffer) {
+            if (buffer.uri === undefined) {
+                return binary;
+            }
+            if (!buffer.uri.startsWith("data:")) {
+                throw new Error("glTF buffers must be embedded: " + buffer.uri);
+            }
+            return bytes(buffer.uri);
+        });
+        function accessor(index) {
+            var accessor = json.accessors[index];
+            var bufferView = json.bufferViews[accessor.bufferView];
+            var buffer = buffers[bufferView.buffer];
+            var size = { SCALAR: 1, VEC3: 3 }[accessor.type];
+            var Type = { 5121: Uint8Array, 5123: Uint16Array, 5125: Uint32Array, 5126: Float32Array }[accessor.componentType];
+            var stride = (bufferView.byteStride || size * Type.BYTES_PER_ELEMENT) / Type.BYTES_PER_ELEMENT;
+            var start = buffer.byteOffset + (bufferView.byteOffset || 0) + (accessor.byteOffset || 0);
+            var end = start + (stride * (accessor.count - 1) + size) * Type.BYTES_PER_ELEMENT;
+            // Copy the data, since typed arrays require aligned offsets.
+            var array = new Type(buffer.buffer.slice(start, end));
+            var result = [];
+            for (var i = 0; i < accessor.count; i++) {
+                for (var j = 0; j < size; j++) {
+                    result.push(array[i * stride + j]);
+                }
+            }
+            return result;
+        }
+        // The transform of a node relative to its parent.
+        function transform(node) {
+            if (node.matrix) {
+                return node.matrix;
+            }
+            var t = node.translation || [0, 0, 0];
+            var r = node.rotation || [0, 0, 0, 1];
+            var s = node.scale || [1, 1, 1];
+            var x = r[0], y = r[1], z = r[2], w = r[3];
+            return [
+                (1 - 2 * (y * y + z * z)) * s[0], 2 * (x * y + z * w) * s[0], 2 * (x * z - y * w) * s[0], 0,
+                2 * (x * y - z * w) * s[1], (1 - 2 * (x * x + z * z)) * s[1], 2 * (y * z + x * w) * s[1], 0,
+                2 * (x * z + y * w) * s[2], 2 * (y * z - x * w) * s[2], (1 - 2 * (x * x + y * y)) * s[2], 0,
+                t[0], t[1], t[2], 1
+            ];
+        }
+        var positions = [];
+        function visit(node, parent) {
+            var m = multiply(parent, transform(node));
+            if (node.mesh !== undefined) {
+                json.meshes[node.mesh].primitives.forEach(function(primitive) {
+                    // Only triangles are supported.
+                    if (primitive.mode !== undefined && primitive.mode !== 4) {
+                        return;
+                    }
+                    var vertices = accessor(primitive.attributes.POSITION);
+                    var indices = primitive.indices === undefined ? null : accessor(primitive.indices);
+                    var count = indices ? indices.length : vertices.length / 3;
+                    for (var i = 0; i < count; i++) {
+                        var v = (indices ? indices[i] : i) * 3;
+                        var x = vertices[v], y = vertices[v + 1], z = vertices[v + 2];
+                        positions.push(
+                            m[0] * x + m[4] * y + m[8] * z + m[12],
+                            m[1] * x + m[5] * y + m[9] * z + m[13],
+                            m[2] * x + m[6] * y + m[10] * z + m[14]
+                        );
+                    }
+                });
+            }
+            (node.children || []).forEach(function(child) {
+                visit(json.nodes[child], m);
+            });
+        }
+        var identity = [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1];
+        var scene = json.scenes && json.scenes[json.scene || 0];
+        var roots = scene
+            ? scene.nodes.map(function(index) { return json.nodes[index]; })
+            : (json.meshes || []).map(function(_, index) { return { mesh: index }; });
+        roots.forEach(function(node) {
+            visit(node, identity);
+        });
+        return positions;
+    }
+    var parsers = {
+        "model/gltf-binary": gltf,
+        "model/gltf+json": gltf,
+        "model/stl": stl,
+        "model/obj": obj
+    };
+    function shader(gl, type, source) {
+        var shader = gl.createShader(type);
+        gl.shaderSource(shader, source);
+        gl.compileShader(shader);
+        return shader;
+    }
+    function render(container, positions) {
+        if (positions.length === 0) {
+            throw new Error("the model has no triangles");
+        }
+        var canvas = document.createElement("canvas");
+        var gl = canvas.getContext("webgl");
+        if (!gl) {
+            throw new Error("WebGL is not supported");
+        }
+        // Flat normals, and the bounds which the camera is fitted to.
+        var normals = new Float32Array(positions.length);
+        var min = [Infinity, Infinity, Infinity];
+        var max = [-Infinity, -Infinity, -Infinity];
+        for (var i = 0; i < positions.length; i += 9) {
+            var p = positions;
+            var ux = p[i + 3] - p[i], uy = p[i + 4] - p[i + 1], uz = p[i + 5] - p[i + 2];
+            var vx = p[i + 6] - p[i], vy = p[i + 7] - p[i + 1], vz = p[i + 8] - p[i + 2];
+            var nx = uy * vz - uz * vy, ny = uz * vx - ux * vz, nz = ux * vy - uy * vx;
+            var len = Math.sqrt(nx * nx + ny * ny + nz * nz) || 1;
+            for (var j = 0; j < 9; j += 3) {
+                normals[i + j] = nx / len;
+                normals[i + j + 1] = ny / len;
+                normals[i + j + 2] = nz / len;
+                for (var k = 0; k < 3; k++) {
+                    min[k] = Math.min(min[k], p[i + j + k]);
+                    max[k] = Math.max(max[k], p[i + j + k]);
+                }
+            }
+        }
+        var center = [0, 1, 2].map(function(k) { return (min[k] + max[k]) / 2; });
+        var radius = Math.sqrt([0, 1, 2].reduce(function(sum, k) {
+            return sum + Math.pow(max[k] - min[k], 2);
+        }, 0)) / 2 || 1;
+        var program = gl.createProgram();
+        gl.attachShader(program, shader(gl, gl.VERTEX_SHADER,
+            "attribute vec3 position; attribute vec3 normal; uniform mat4 view; uniform mat4 projection;" +
+            "varying vec3 n; void main() { n = (view * vec4(normal, 0.0)).xyz;" +
+            "gl_Position = projection * view * vec4(position, 1.0); }"));
+        gl.attachShader(program, shader(gl, gl.FRAGMENT_SHADER,
+            "precision mediump float; uniform vec3 color; varying vec3 n; void main() {" +
+            "float light = 0.35 + 0.65 * abs(dot(normalize(n), normalize(vec3(0.4, 0.6, 0.7))));" +
+            "gl_FragColor = vec4(color * light, 1.0); }"));
+        gl.linkProgram(program);
+        gl.useProgram(program);
+        [["position", new Float32Array(positions)], ["normal", normals]].forEach(function(attribute) {
+            var location = gl.getAttribLocation(program, attribute[0]);
+            gl.bindBuffer(gl.ARRAY_BUFFER, gl.createBuffer());
+            gl.bufferData(gl.ARRAY_BUFFER, attribute[1], gl.STATIC_DRAW);
+            gl.enableVertexAttribArray(location);
+            gl.vertexAttribPointer(location, 3, gl.FLOAT, false, 0, 0);
+        });
+        gl.enable(gl.DEPTH_TEST);
+        var yaw = -0.6;
+        var pitch = 0.4;
+        var distance = radius * 2.8;
+        function draw() {
+            var ratio = window.devicePixelRatio || 1;
+            canvas.width = Math.max(1, container.clientWidth * ratio);
+            canvas.height = Math.max(1, container.clientHeight * ratio);
+            gl.viewport(0, 0, canvas.width, canvas.height);
+            gl.clearColor(0, 0, 0, 0);
+            gl.clear(gl.COLOR_BUFFER_BIT | gl.DEPTH_BUFFER_BIT);
+            var color = getComputedStyle(container).color.match(/[\d.]+/g).slice(0, 3).map(function(c) {
+                return c / 255;
+            });
+            gl.uniform3fv(gl.getUniformLocation(program, "color"), color);
+            var cy = Math.cos(yaw), sy = Math.sin(yaw), cp = Math.cos(pitch), sp = Math.sin(pitch);
+            var rotation = multiply(
+                [1, 0, 0, 0, 0, cp, sp, 0, 0, -sp, cp, 0, 0, 0, 0, 1],
+                [cy, 0, -sy, 0, 0, 1, 0, 0, sy, 0, cy, 0, 0, 0, 0, 1]
+            );
+            var view = multiply(
+                [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, -distance, 1],
+                multiply(rotation, [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, -center[0], -center[1], -center[2], 1])
+            );
+            var f = 1 / Math.tan(Math.PI / 8);
+            var near = distance / 100, far = distance + radius * 2;
+            var projection = [
+                f * canvas.height / canvas.width, 0, 0, 0,
+                0, f, 0, 0,
+                0, 0, (far + near) / (near - far), -1,
+                0, 0, 2 * far * near / (near - far), 0
+            ];
+            gl.uniformMatrix4fv(gl.getUniformLocation(program, "view"), false, view);
+            gl.uniformMatrix4fv(gl.getUniformLocation(program, "projection"), false, projection);
+            gl.drawArrays(gl.TRIANGLES, 0, positions.length / 3);
+        }
+        if (container.getAttribute("data-controls") === "orbit") {
+            var last = null;
+            canvas.style.cursor = "grab";
+            canvas.style.touchAction = "none";
+            canvas.addEventListener("pointerdown", function(event) {
+                last = [event.clientX, event.clientY];
+                canvas.setPointerCapture(event.pointerId);
+            });
+            canvas.addEventListener("pointermove", function(event) {
+                if (last === null) {
+                    return;
+                }
+                yaw += (event.clientX - last[0]) * 0.01;
+                pitch = Math.max(-1.5, Math.min(1.5, pitch + (event.clientY - last[1]) * 0.01));
+                last = [event.clientX, event.clientY];
+                draw();
+            });
+            canvas.addEventListener("pointerup", function() {
+                last = null;
+            });
+            canvas.addEventListener("wheel", function(event) {
+                event.preventDefault();
+                distance = Math.max(radius * 0.5, Math.min(radius * 20, distance * Math.exp(event.deltaY * 0.001)));
+                draw();
+            });
+        }
+        canvas.style.width = "100%";
+        canvas.style.height = "100%";
+        container.textContent = "";
+        container.appendChild(canvas);
+        draw();
+        window.addEventListener("resize", draw);
+        // The color follows the theme.
+        new MutationObserver(draw).observe(document.documentElement, {
+            attributes: true,
+            attributeFilter: ["data-theme"]
+        });
+    }
+    // Show a model, given as a data URL, in its container. The poster or alt text
+    // stays in place if the model can't be shown.
+    window.docextViewer = function(container, url) {
+        if (container.hasAttribute("data-viewer")) {
+            return;
+        }
+        container.setAttribute("data-viewer", "");
+        try {
+            var mime = url.slice(5, url.search(/[;,]/));
+            render(container, parsers[mime](bytes(url)));
+        } catch (err) {
+            console.warn("docext: failed to show 3D model:", err);
+        }
+    };
+    // Models linked to their data directly are shown right away, others once the
+    // image script has loaded their data.
+    function start() {
+        document.addEventListener("DOMContentLoaded", function() {
+            elem.querySelectorAll(".docext-model[data-model^='data:']").forEach(function(container) {
+                window.docextViewer(container, container.getAttribute("data-model"));
+            });
+        });
+    }
+    start();
+})()
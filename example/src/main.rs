@@ -142,6 +142,265 @@ pub trait BrokenItalics {
 #[docext]
 pub trait MathInCode {}
 
+/// Should render a numbered theorem, a lemma, a definition and two collapsible
+/// proofs ending with a QED mark.
+///
+/// > **Theorem (Pythagoras).** For a right triangle with legs $a$, $b$ and
+/// > hypotenuse $c$, we have $a^2 + b^2 = c^2$.
+///
+/// > **Proof.** Rearrange four copies of the triangle inside of a square.
+///
+/// :::lemma Triangle inequality
+/// For all $x, y$ we have $|x + y| \le |x| + |y|$.
+/// :::
+///
+/// :::proof
+/// Square both sides.
+/// :::
+///
+/// > **Definition.** A number $n$ is _even_ if $2 \mid n$.
+///
+/// > **Theorem.** This one should be numbered 2.
+///
+/// ```text
+/// :::theorem
+/// This is a code block, not a theorem.
+/// :::
+/// ```
+#[docext]
+pub trait Theorems {}
+
+/// Should render five styled callouts, one of each kind.
+///
+/// > [!NOTE]
+/// > Useful information that users should know, even when skimming content.
+///
+/// > [!TIP]
+/// > Helpful advice for doing things better or more easily.
+///
+/// > [!IMPORTANT]
+/// > Key information users need to know, with math: $e^{i\pi} = -1$.
+///
+/// > [!WARNING]
+/// > Urgent info that needs immediate user attention to avoid problems.
+/// > - Even with lists.
+///
+/// > [!CAUTION]
+/// > Advises about risks or negative outcomes of certain actions.
+///
+/// > This is a regular blockquote.
+#[docext]
+pub trait Admonitions {}
+
+/// Should cite Golub and Van Loan as [1] and Knuth as [2], followed by a
+/// "References" section listing both.
+///
+/// The QR algorithm is described in [@golub2013, p. 42], see also
+/// [@knuth1984; @golub2013]. Not a citation: `[@golub2013]`, nor is
+/// [@knuth1984](https://example.com). Math is left alone: $[@a]$.
+#[docext]
+pub trait Citations {}
+
+/// Should link each identifier: doi:10.1137/1.9781611971446, arXiv:2101.00001,
+/// arXiv:math/0211159v2, ISBN:978-0-89871-361-9 and OEIS:A000045.
+///
+/// Identifiers in code and math are not linked: `doi:10.1137/1.9781611971446`
+/// and $\text{OEIS:A000045}$.
+#[docext]
+pub trait Identifiers {}
+
+/// Should render a table of contents below, with the $\pi$ rendered as math.
+///
+/// [[toc]]
+///
+/// # Introduction
+///
+/// Some text.
+///
+/// ## The $\pi$ constant
+///
+/// More text.
+///
+/// ### Digits
+///
+/// # Conclusion
+///
+/// Done.
+#[docext]
+pub trait TableOfContents {}
+
+/// Should render a table of contents after this summary, even without a marker.
+///
+/// # First
+///
+/// # Second
+#[docext(toc)]
+pub trait TableOfContentsAttr {}
+
+/// Should render a circle and the text "docext" in the text color of the
+/// current theme, without showing any alerts.
+///
+/// ![circle](img/circle.svg)
+#[docext]
+pub trait SvgImages {}
+
+/// Should show the first pepega with light themes and the second one with dark
+/// themes, switching without a reload when the theme changes. The image below
+/// it should read "light theme" or "dark theme", following the same rule.
+///
+/// ![pepega](img/pepega.png "dark=img/pepega2.png")
+///
+/// ![theme](img/theme.svg)
+#[docext]
+pub trait ThemeImages {}
+
+/// Should show a centered pepega 100 pixels wide, followed by a paragraph with
+/// a pepega floating to the right at 3em high. The attribute braces should not
+/// be visible.
+///
+/// ![pepega](img/pepega.png){width=100 align=center}
+///
+/// ![pepega](img/pepega2.png){height=3em align=right .small} Text next to the
+/// floating image.
+#[docext]
+pub trait ImageAttributes {}
+
+/// Should show two centered figures captioned "Figure 1: A circle" and "Figure
+/// 2: The current theme". This sentence should link to Figure 2 and then to
+/// Figure 1: see @fig:theme and @fig:circle.
+///
+/// ![Figure: A circle](img/circle.svg){#fig:circle}
+///
+/// ![Figure: The current theme](img/theme.svg){#fig:theme width=200}
+#[docext]
+pub trait Figures {}
+
+/// Should link to the second figure of the `Figures` trait, with the text
+/// "Figure": [@fig:theme](crate::Figures).
+#[docext]
+pub trait FigureReferences {}
+
+/// Should show a pepega from an HTML image tag, followed by the theme image
+/// from a picture element, which should follow the theme like the image in
+/// `ThemeImages`.
+///
+/// <img src="img/pepega.png" alt="pepega" width="64">
+///
+/// <picture>
+///   <source srcset="img/theme.svg" type="image/svg+xml">
+///   <img src="img/pepega2.png" alt="fallback">
+/// </picture>
+#[docext]
+pub trait HtmlImages {}
+
+/// Should show a 32 pixel square which is red on regular displays and blue on
+/// high density displays, twice: once from the `@2x` file next to it, once
+/// from an explicit srcset.
+///
+/// ![density](img/density.png)
+///
+/// ![density](img/density.png){srcset="img/density.png 1x, img/density@2x.png
+/// 2x" width=32}
+#[docext]
+pub trait HighDensityImages {}
+
+/// Should show a green "workspace asset" badge, loaded from the `assets`
+/// directory at the root of the workspace.
+///
+/// ![badge](/assets/badge.svg)
+#[docext]
+pub trait WorkspaceImages {}
+
+/// Should show the "odd name's ü" badge three times, although its path contains
+/// spaces, a quote and unicode, and is spelled differently each time.
+///
+/// ![odd](<img/odd name's ü.svg>)
+///
+/// ![odd](img/odd%20name's%20%C3%BC.svg)
+///
+/// <img src="img/odd name&#39;s ü.svg" alt="odd">
+#[docext]
+pub trait OddPaths {}
+
+/// Should show a blue "next to the source" badge, found next to this file, and
+/// a pepega, found relative to the crate root.
+///
+/// ![source](source.svg)
+///
+/// ![pepega](img/pepega.png)
+#[docext]
+pub trait RelativeImages {}
+
+/// Should show a pepega, found relative to the `img` directory given as the
+/// base.
+///
+/// ![pepega](pepega.png)
+#[docext(base = "img")]
+pub trait BaseImages {}
+
+/// Should show a pepega, a 32 pixel square which is red on regular displays and
+/// blue on high density displays, the theme image as a figure, and another
+/// pepega from an HTML image tag, all without JavaScript. The theme image
+/// doesn't change with the theme.
+///
+/// ![pepega](img/pepega.png)
+///
+/// ![density](img/density.png){width=32}
+///
+/// ![Figure: The theme image](img/theme.svg "dark=img/theme.dark.svg")
+///
+/// <img src="img/pepega.png" alt="pepega">
+#[docext(data_urls)]
+pub trait DataUrls {}
+
+/// Should show an audio player, which plays a short beep, with the same player
+/// as a figure below it.
+///
+/// ![A short beep](media/beep.wav)
+///
+/// ![Figure: The beep again](media/beep.wav){#fig:beep}
+#[docext]
+pub trait Audio {}
+
+/// Should show a link which downloads `coeffs.csv`, with the reference data,
+/// and a link to the coefficient section of this page.
+///
+/// Download the [reference *data*](data/coeffs.csv "Coefficients") or read
+/// about the [coefficients](#coefficients).
+///
+/// # Coefficients
+///
+/// The coefficients halve with every step.
+#[docext]
+pub trait Attachments {}
+
+/// Should show three 3D models in the text color of the theme: an L-shaped
+/// bracket which can be rotated by dragging and zoomed by scrolling, a cube
+/// and a slightly rotated pyramid. Without WebGL, a gray L is shown instead of
+/// the bracket.
+///
+/// ![A mounting bracket](models/bracket.stl){poster=img/bracket.png
+/// controls=orbit}
+///
+/// ![A cube](models/cube.obj){width=200 height=200}
+/// ![A pyramid](models/pyramid.glb){width=200 height=200}
+#[docext]
+pub trait Models {}
+
+/// Holds associated items with images. Editing the images should rebuild the
+/// docs of the associated items as well.
+pub struct ImagesInImpl;
+
+impl ImagesInImpl {
+    /// ![pepega2](img/pepega2.png)
+    #[docext]
+    pub const CONST: usize = 1;
+
+    /// ![pepega](img/pepega.png)
+    #[docext]
+    pub fn method() {}
+}
+
 /// Top-level function comment.
 /// $$
 /// x = \pi